@@ -0,0 +1,133 @@
+use std::{borrow::Cow, hint::black_box};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use gif::{ColorOutput, DecodeOptions, DisposalMethod, Encoder, Frame};
+use gif_compressor::{
+    image::{GifFrame, Image, Rgb, Rgba},
+    reader::{CanvasFit, DecodeMode, GifReader},
+};
+use rand::{RngExt, SeedableRng, rngs::StdRng};
+
+const WIDTH: u16 = 1920;
+const HEIGHT: u16 = 1080;
+const FRAMES: u16 = 10;
+
+/// a 1080p screen recording: a noisy full first frame, then partial updates cycling through every
+/// disposal method
+fn make_gif() -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let palette: Vec<u8> = (0..256 * 3).map(|_| rng.random()).collect();
+    let mut bytes = Vec::new();
+    let mut encoder = Encoder::new(&mut bytes, WIDTH, HEIGHT, &palette).unwrap();
+    let disposals = [
+        DisposalMethod::Keep,
+        DisposalMethod::Previous,
+        DisposalMethod::Background,
+        DisposalMethod::Any,
+    ];
+    for k in 0..FRAMES {
+        let (width, height) = if k == 0 {
+            (WIDTH, HEIGHT)
+        } else {
+            (WIDTH / 3, HEIGHT / 3)
+        };
+        let buffer: Vec<u8> = (0..width as usize * height as usize)
+            .map(|_| rng.random())
+            .collect();
+        encoder
+            .write_frame(&Frame {
+                width,
+                height,
+                top: (k * 37) % (HEIGHT - height + 1),
+                left: (k * 131) % (WIDTH - width + 1),
+                dispose: disposals[k as usize % disposals.len()],
+                transparent: Some(0),
+                buffer: Cow::Owned(buffer),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    drop(encoder);
+    bytes
+}
+
+/// the compositor GifReader used to have, kept as a baseline: every frame starts from a clone of the
+/// previous canvas, and the whole frame is cloned again to apply its disposal
+fn clone_per_frame(bytes: &[u8]) {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let mut decoder = options.read_info(bytes).unwrap();
+    let (height, width) = (decoder.height() as usize, decoder.width() as usize);
+    let parse_palette = |palette_raw: &[u8]| -> Vec<Rgb> {
+        let mut palette: Vec<Rgb> = palette_raw
+            .chunks_exact(3)
+            .map(|c| Rgb::new(c[0], c[1], c[2]))
+            .collect();
+        palette.sort();
+        palette.dedup();
+        palette
+    };
+    let global_palette = decoder.global_palette().map(parse_palette);
+    let mut prev_frame: Option<Image> = None;
+    while let Some(frame_raw) = decoder.read_next_frame().unwrap() {
+        let mut new_frame = prev_frame.clone().unwrap_or(Image::blank(height, width));
+        let pixels_raw: Vec<(u8, u8, u8, u8)> = frame_raw
+            .buffer
+            .chunks_exact(4)
+            .map(|c| (c[0], c[1], c[2], c[3]))
+            .collect();
+        let (top, left) = (frame_raw.top as usize, frame_raw.left as usize);
+        let (frame_height, frame_width) = (frame_raw.height as usize, frame_raw.width as usize);
+        for i in 0..frame_height {
+            for j in 0..frame_width {
+                let (r, g, b, a) = pixels_raw[i * frame_width + j];
+                if a == 0 {
+                    continue;
+                }
+                *new_frame.get_mut(top + i, left + j) = Rgba::new(r, g, b, a);
+            }
+        }
+        let palette = match &frame_raw.palette {
+            Some(local) => parse_palette(local),
+            None => global_palette.clone().unwrap(),
+        };
+        let frame = GifFrame::new(new_frame, palette, frame_raw.delay);
+        let mut new_prev = frame.clone();
+        let blank = Image::blank(height, width);
+        let before_frame = prev_frame.as_ref().unwrap_or(&blank);
+        for i in top..top + frame_height {
+            for j in left..left + frame_width {
+                *new_prev.image.get_mut(i, j) = match frame_raw.dispose {
+                    DisposalMethod::Any | DisposalMethod::Keep => new_prev.image.get(i, j),
+                    DisposalMethod::Background => blank.get(i, j),
+                    DisposalMethod::Previous => before_frame.get(i, j),
+                }
+            }
+        }
+        prev_frame = Some(new_prev.image);
+        black_box(frame);
+    }
+}
+
+fn bench_reader(c: &mut Criterion) {
+    let bytes = make_gif();
+    let mut group = c.benchmark_group("reader");
+    group.sample_size(10);
+    group.bench_function("decode and composite 1080p", |b| {
+        b.iter(|| {
+            let reader =
+                GifReader::new(bytes.as_slice(), DecodeMode::Strict, CanvasFit::Clip).unwrap();
+            for frame in reader {
+                black_box(frame.unwrap());
+            }
+        })
+    });
+    group.bench_function(
+        "decode and composite 1080p, cloning the canvas per frame",
+        |b| b.iter(|| clone_per_frame(&bytes)),
+    );
+    group.finish();
+}
+
+criterion_group!(benches, bench_reader);
+criterion_main!(benches);
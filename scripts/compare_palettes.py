@@ -0,0 +1,75 @@
+import logging
+import re
+import subprocess
+import tempfile
+import time
+from pathlib import Path
+
+from download_examples import EXAMPLES_PATH
+
+logging.basicConfig(level=logging.INFO)
+logger = logging.getLogger(__name__)
+
+VARIANTS = {
+    "median-cut": ["--palette-algorithm", "median-cut"],
+    "weighted-median-cut": ["--palette-algorithm", "weighted-median-cut"],
+    "wu": ["--palette-algorithm", "wu"],
+    "octree": ["--palette-algorithm", "octree"],
+    "weighted oklab": [
+        "--palette-algorithm",
+        "weighted-median-cut",
+        "--colour-space",
+        "oklab",
+    ],
+    "weighted + k-means": [
+        "--palette-algorithm",
+        "weighted-median-cut",
+        "--kmeans-iterations",
+        "10",
+    ],
+}
+# the last one logged is after any refinement
+MEAN_ERROR = re.compile(r"mean palette error(?: after k-means)?: ([\d.]+)")
+
+
+def main() -> None:
+    subprocess.run(["cargo", "build", "--release"], check=True)  # noqa: S607
+    with tempfile.TemporaryDirectory() as tmp:
+        output = Path(tmp) / "output.gif"
+        for file in sorted(EXAMPLES_PATH.iterdir()):
+            if not file.name.endswith(".gif") or file.name.endswith("_output.gif"):
+                continue
+            logger.info("%s", file.name)
+            for variant, args in VARIANTS.items():
+                start = time.perf_counter()
+                result = subprocess.run(  # noqa: S603
+                    [  # noqa: S607
+                        "cargo",
+                        "run",
+                        "--release",
+                        "--",
+                        "-i",
+                        file,
+                        "-o",
+                        output,
+                        "-v",
+                        *args,
+                    ],
+                    check=True,
+                    capture_output=True,
+                    text=True,
+                )
+                elapsed = time.perf_counter() - start
+                errors = MEAN_ERROR.findall(result.stderr)
+                error = errors[-1] if errors else "?"
+                logger.info(
+                    "  %-20s mean error %7s  %8d bytes  %6.2fs",
+                    variant,
+                    error,
+                    output.stat().st_size,
+                    elapsed,
+                )
+
+
+if __name__ == "__main__":
+    main()
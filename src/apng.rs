@@ -0,0 +1,383 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+};
+
+use gif::{DisposalMethod, Repeat};
+use log::warn;
+use png::{
+    BitDepth, BlendOp, ColorType, Decoder, DisposeOp, Encoder, FrameControl, Transformations,
+};
+
+use crate::{
+    error::{Error, Result},
+    image::{Disposal, GifFrame, Image, Rgb, Rgba},
+    metadata::GifMetadata,
+    reader::{DecodeMode, parse_palette},
+    source::{self, DecodeFrame, FrameCursor, FrameSource},
+    transparency::TransparencyOutput,
+};
+
+/// decodes an apng, or a still png as a single frame. each gifframe created will have the same
+/// height/width
+pub struct ApngReader {
+    height: usize,
+    width: usize,
+    /// the PLTE chunk, if the pixels are limited to it
+    palette: Option<Vec<Rgb>>,
+    compositor: Compositor,
+    reader: png::Reader<Cursor<Vec<u8>>>,
+    buffer: Vec<u8>,
+    repeat: Repeat,
+    cursor: FrameCursor,
+    frame_count: usize,
+    /// in exact centiseconds, so that rounding each frame's delay doesn't drift over the animation
+    elapsed: f64,
+    elapsed_rounded: u64,
+}
+impl ApngReader {
+    pub fn new(mut input: impl Read, decode_mode: DecodeMode) -> Result<Self> {
+        // the png decoder needs to seek, so the input is read all at once
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        let mut decoder = Decoder::new(Cursor::new(bytes));
+        decoder
+            .set_transformations(Transformations::normalize_to_color8() | Transformations::ALPHA);
+        let mut reader = decoder.read_info()?;
+        let info = reader.info();
+        let height = info.height as usize;
+        let width = info.width as usize;
+        let palette = match info.color_type {
+            ColorType::Indexed => info.palette.as_deref().map(parse_palette),
+            _ => None,
+        };
+        let (frame_count, repeat) = match &info.animation_control {
+            Some(animation) => (
+                animation.num_frames as usize,
+                match animation.num_plays {
+                    0 => Repeat::Infinite,
+                    n => Repeat::Finite((n - 1).min(u16::MAX as u32) as u16),
+                },
+            ),
+            None => (1, Repeat::Finite(0)),
+        };
+        // shown by decoders without apng support, but not part of the animation
+        let has_default_image = info.animation_control.is_some() && info.frame_control.is_none();
+        let buffer_size = reader
+            .output_buffer_size()
+            .ok_or_else(|| Error::MalformedPng("image is too large".to_string()))?;
+        let mut buffer = vec![0; buffer_size];
+        if has_default_image {
+            reader.next_frame(&mut buffer)?;
+        }
+        Ok(Self {
+            height,
+            width,
+            palette,
+            compositor: Compositor::new(height, width),
+            reader,
+            buffer,
+            repeat,
+            cursor: FrameCursor::new(decode_mode),
+            frame_count,
+            elapsed: 0.0,
+            elapsed_rounded: 0,
+        })
+    }
+}
+impl DecodeFrame for ApngReader {
+    fn decode_frame(&mut self, index: usize) -> Result<Option<GifFrame>> {
+        if index >= self.frame_count {
+            return Ok(None);
+        }
+        let output = self.reader.next_frame(&mut self.buffer)?;
+        // a still png has no frame control, so it's one frame covering the whole canvas
+        let control = self.reader.info().frame_control.unwrap_or(FrameControl {
+            width: output.width,
+            height: output.height,
+            delay_num: 0,
+            ..FrameControl::default()
+        });
+        let channels = output.color_type.samples();
+        let rows = self
+            .buffer
+            .chunks_exact(output.line_size)
+            .take(output.height as usize)
+            .map(|row| {
+                row.chunks_exact(channels)
+                    .take(output.width as usize)
+                    .map(|c| match *c {
+                        [l] => [l, l, l, 255],
+                        [l, a] => [l, l, l, a],
+                        [r, g, b] => [r, g, b, 255],
+                        [r, g, b, a] => [r, g, b, a],
+                        _ => unreachable!("png pixel with {} channels", c.len()),
+                    })
+            });
+        // the first frame has nothing to restore, so the spec treats previous like background
+        let dispose = match control.dispose_op {
+            DisposeOp::Previous if index == 0 => DisposeOp::Background,
+            dispose => dispose,
+        };
+        let image = self.compositor.draw(rows, &control, dispose);
+
+        let delay_den = if control.delay_den == 0 {
+            100
+        } else {
+            control.delay_den
+        };
+        self.elapsed += control.delay_num as f64 * 100.0 / delay_den as f64;
+        let shown_until = self.elapsed.round() as u64;
+        let delay = (shown_until - self.elapsed_rounded).min(u16::MAX as u64) as u16;
+        self.elapsed_rounded = shown_until;
+
+        let frame = GifFrame {
+            dispose: match dispose {
+                DisposeOp::None => Disposal::Keep,
+                DisposeOp::Background => Disposal::Background,
+                DisposeOp::Previous => Disposal::Previous,
+            },
+            ..GifFrame::new(image, self.palette.clone().unwrap_or_default(), delay)
+        };
+        Ok(Some(frame))
+    }
+    fn cursor(&mut self) -> &mut FrameCursor {
+        &mut self.cursor
+    }
+}
+impl FrameSource for ApngReader {
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn metadata(&self) -> GifMetadata {
+        GifMetadata {
+            repeat: self.repeat,
+            ..GifMetadata::default()
+        }
+    }
+    fn is_paletted(&self) -> bool {
+        self.palette.is_some()
+    }
+}
+impl Iterator for ApngReader {
+    type Item = Result<GifFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        source::decode_next(self)
+    }
+}
+
+/// like the gif compositor, but frames can be partially transparent and blended over the canvas, so
+/// the canvas keeps full alpha until it's output
+struct Compositor {
+    canvas: Vec<[u8; 4]>,
+    height: usize,
+    width: usize,
+    /// what was under the last frame, if it's to be disposed of by restoring it
+    saved_region: Vec<[u8; 4]>,
+}
+impl Compositor {
+    fn new(height: usize, width: usize) -> Self {
+        Self {
+            canvas: vec![[0; 4]; height * width],
+            height,
+            width,
+            saved_region: Vec::new(),
+        }
+    }
+    /// returns the canvas with the frame drawn over it, with alpha rounded to opaque or transparent
+    fn draw(
+        &mut self,
+        rows: impl Iterator<Item = impl Iterator<Item = [u8; 4]>>,
+        control: &FrameControl,
+        dispose: DisposeOp,
+    ) -> Image {
+        let (top, left) = (control.y_offset as usize, control.x_offset as usize);
+        let visible_height = (control.height as usize).min(self.height.saturating_sub(top));
+        let visible_width = (control.width as usize).min(self.width.saturating_sub(left));
+        let canvas_width = self.width;
+        let region_rows = (top..top + visible_height)
+            .map(|i| i * canvas_width + left..i * canvas_width + left + visible_width);
+        if dispose == DisposeOp::Previous {
+            self.saved_region.clear();
+            for row in region_rows.clone() {
+                self.saved_region.extend_from_slice(&self.canvas[row]);
+            }
+        }
+        for (row, frame_row) in region_rows.clone().zip(rows) {
+            for (pixel, c) in self.canvas[row].iter_mut().zip(frame_row) {
+                *pixel = match control.blend_op {
+                    BlendOp::Source => c,
+                    BlendOp::Over => blend_over(c, *pixel),
+                };
+            }
+        }
+        let composited = Image {
+            height: self.height,
+            width: self.width,
+            buffer: self
+                .canvas
+                .iter()
+                .map(|&[r, g, b, a]| {
+                    if a < 128 {
+                        Rgba::TRANSPARENT
+                    } else {
+                        Rgba::new(r, g, b, 255)
+                    }
+                })
+                .collect(),
+        };
+        match dispose {
+            DisposeOp::None => {}
+            DisposeOp::Background => {
+                for row in region_rows {
+                    self.canvas[row].fill([0; 4]);
+                }
+            }
+            DisposeOp::Previous => {
+                for (row, saved_row) in
+                    region_rows.zip(self.saved_region.chunks_exact(visible_width.max(1)))
+                {
+                    self.canvas[row].copy_from_slice(saved_row);
+                }
+            }
+        }
+        composited
+    }
+}
+/// the png "over" operator on non-premultiplied colours
+fn blend_over(src: [u8; 4], dst: [u8; 4]) -> [u8; 4] {
+    let src_alpha = src[3] as u32;
+    let dst_alpha = dst[3] as u32 * (255 - src_alpha) / 255;
+    let alpha = src_alpha + dst_alpha;
+    if alpha == 0 {
+        return [0; 4];
+    }
+    let mix =
+        |s: u8, d: u8| ((s as u32 * src_alpha + d as u32 * dst_alpha + alpha / 2) / alpha) as u8;
+    [
+        mix(src[0], dst[0]),
+        mix(src[1], dst[1]),
+        mix(src[2], dst[2]),
+        alpha as u8,
+    ]
+}
+
+/// writes an apng, indexed against the palette if the frames were quantized and rgba otherwise
+pub struct ApngWriter<W: Write, I: Iterator<Item = TransparencyOutput>> {
+    writer: png::Writer<W>,
+    transparency_output: I,
+    /// each colour's palette index, and the transparent index after them
+    index_map: Option<(HashMap<Rgb, u8>, u8)>,
+    width: usize,
+}
+impl<W: Write, I: Iterator<Item = TransparencyOutput>> ApngWriter<W, I> {
+    /// the frame count has to be known up front, since it's in the header
+    pub fn new(
+        transparency_output: I,
+        palette: Option<Vec<Rgb>>,
+        frame_count: usize,
+        height: usize,
+        width: usize,
+        metadata: &GifMetadata,
+        output: W,
+    ) -> Result<Self> {
+        let mut encoder = Encoder::new(output, width as u32, height as u32);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_animated(frame_count as u32, metadata.play_count())?;
+        let index_map = match palette {
+            Some(palette) => {
+                assert!(palette.len() <= 255);
+                let plte: Vec<u8> = palette
+                    .iter()
+                    .flat_map(|x| [x.r, x.g, x.b])
+                    .chain([0, 0, 0])
+                    .collect();
+                let mut trns = vec![255; palette.len()];
+                trns.push(0);
+                encoder.set_color(ColorType::Indexed);
+                encoder.set_palette(plte);
+                encoder.set_trns(trns);
+                let index_map = palette
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| (*x, i as u8))
+                    .collect();
+                Some((index_map, palette.len() as u8))
+            }
+            None => {
+                encoder.set_color(ColorType::Rgba);
+                None
+            }
+        };
+        if metadata
+            .extensions
+            .iter()
+            .any(|extension| !extension.is_comment())
+        {
+            warn!("apng output doesn't keep the input's application extensions");
+        }
+        for extension in metadata
+            .extensions
+            .iter()
+            .filter(|extension| extension.is_comment())
+        {
+            // tEXt is latin-1, which every byte maps to
+            let text = extension
+                .sub_blocks
+                .concat()
+                .into_iter()
+                .map(char::from)
+                .collect();
+            encoder.add_text_chunk("Comment".to_string(), text)?;
+        }
+        Ok(Self {
+            writer: encoder.write_header()?,
+            transparency_output,
+            index_map,
+            width,
+        })
+    }
+    pub fn write_frame(&mut self) -> Result<bool> {
+        let Some((frame, transparent_pixels, dispose)) = self.transparency_output.next() else {
+            return Ok(false);
+        };
+        let mut data = Vec::new();
+        for i in frame.top..frame.top + frame.local_height {
+            for j in frame.left..frame.left + frame.local_width {
+                let index = i * self.width + j;
+                let pixel = frame.image.buffer[index];
+                match &self.index_map {
+                    Some((_, transparent_index)) if transparent_pixels[index] => {
+                        data.push(*transparent_index);
+                    }
+                    Some((index_map, _)) => data.push(index_map[&pixel.rgb()]),
+                    None if transparent_pixels[index] => data.extend_from_slice(&[0; 4]),
+                    None => data.extend_from_slice(&[pixel.r, pixel.g, pixel.b, 255]),
+                }
+            }
+        }
+        // the position is checked against the old dimensions, and vice versa
+        self.writer.reset_frame_position()?;
+        self.writer
+            .set_frame_dimension(frame.local_width as u32, frame.local_height as u32)?;
+        self.writer
+            .set_frame_position(frame.left as u32, frame.top as u32)?;
+        self.writer.set_frame_delay(frame.delay, 100)?;
+        self.writer.set_dispose_op(match dispose {
+            DisposalMethod::Background => DisposeOp::Background,
+            _ => DisposeOp::None,
+        })?;
+        // transparent pixels show the canvas beneath like in a gif
+        self.writer.set_blend_op(BlendOp::Over)?;
+        self.writer.write_image_data(&data)?;
+        Ok(true)
+    }
+    pub fn finish(self) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
@@ -1,11 +1,13 @@
 use std::{
     fs::File,
-    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    io::{self, ErrorKind, Read, Seek, Write},
     marker::PhantomData,
 };
 
 use bitcode::{DecodeOwned, Encode};
 
+use crate::error::Result;
+
 /// handles writing a type T to, and then reading that type T from, a file in chunks
 pub struct ChunkedFile<'a, T: Encode + DecodeOwned> {
     file: &'a mut File,
@@ -23,46 +25,50 @@ where
             _marker: PhantomData,
         }
     }
-    pub fn finish_writing(&mut self) {
+    pub fn finish_writing(&mut self) -> Result<()> {
         self.finished_writing = true;
-        self.file.rewind().unwrap();
+        self.file.rewind()?;
+        Ok(())
     }
-    //TODO: is it bad to unwrap() all of these io tasks
-    pub fn size(&self) -> u64 {
-        self.file.metadata().unwrap().len()
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len())
     }
-    pub fn write_chunk(&mut self, chunk: T) {
+    pub fn write_chunk(&mut self, chunk: &T) -> Result<()> {
         if self.finished_writing {
             panic!("attempt to call write_chunk when finished_writing=true");
         }
-        let bytes = bitcode::encode(&chunk);
-        self.file
-            .write_all(&(bytes.len() as u64).to_le_bytes())
-            .unwrap();
-        self.file.write_all(&bytes).unwrap();
+        let bytes = bitcode::encode(chunk);
+        self.file.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        self.file.write_all(&bytes)?;
+        Ok(())
+    }
+    fn read_chunk(&mut self) -> Result<Option<T>> {
+        let mut size_bytes = [0; 8];
+        match self.file.read_exact(&mut size_bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let size = u64::from_le_bytes(size_bytes) as usize;
+        let mut bytes = vec![0; size];
+        self.file.read_exact(&mut bytes)?;
+        let chunk = bitcode::decode(&bytes)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        Ok(Some(chunk))
     }
 }
 impl<'a, T> Iterator for ChunkedFile<'a, T>
 where
     T: Encode + DecodeOwned,
 {
-    type Item = T;
+    type Item = Result<T>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if !self.finished_writing {
             panic!("attempt to read a chunk when finished_writing=false");
         }
-        let mut size_bytes = [0; 8];
-        match self.file.read_exact(&mut size_bytes) {
-            Ok(()) => {}
-            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
-                return None;
-            }
-            Err(e) => panic!("failed to read chunk: {}", e),
-        }
-        let size = u64::from_le_bytes(size_bytes) as usize;
-        let mut bytes = vec![0; size];
-        self.file.read_exact(&mut bytes).unwrap();
-        Some(bitcode::decode(&bytes).unwrap())
+        self.read_chunk().transpose()
     }
 }
@@ -1,17 +1,78 @@
-use clap::Parser;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, ValueEnum};
 use clap_verbosity_flag::{Verbosity, WarnLevel};
+use gif::Repeat;
+use gif_compressor::{
+    colour_space::ColourSpace,
+    decimate::DecimateRate,
+    frame_rate::FrameRate,
+    palette::{KmeansOptions, PaletteAlgorithm},
+    reader::{CanvasFit, DecodeMode},
+    sequence,
+    trim::FrameRange,
+    y4m::YuvMatrix,
+};
 
 #[derive(Debug, Clone, Parser)]
 #[command(version, about, long_about=None)]
 pub struct Cli {
-    /// The input file path.
+    /// The input file path, or - for stdin. PNG sequences are read from a directory or a pattern like
+    /// frame_%04d.png.
     #[arg(short, long)]
     pub input: String,
 
-    /// The output file path.
+    /// The output file path, or - for stdout.
     #[arg(short, long)]
     pub output: String,
 
+    /// The output's format. Detected from the output path's extension if not given, defaulting to
+    /// GIF.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Keep every undithered colour instead of reducing them to a palette. Only for APNG and WebP
+    /// output.
+    #[arg(long)]
+    pub no_quantize: bool,
+
+    /// How many colours the palette can have, including one kept for transparency. Smaller palettes
+    /// use shorter LZW codes, so fewer colours compress better.
+    #[arg(
+        long = "colors",
+        alias = "colours",
+        default_value_t = 256,
+        value_parser = clap::value_parser!(u16).range(2..=256)
+    )]
+    pub colours: u16,
+
+    /// Generate a palette for every group of this many frames, written as local colour tables, instead
+    /// of one for the whole GIF. 1 gives every frame its own palette. APNG output is written as RGBA
+    /// instead.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub local_palettes: Option<u64>,
+
+    /// How to pick the palette from the undithered colours.
+    #[arg(long, value_enum, default_value_t = PaletteAlgorithm::MedianCut)]
+    pub palette_algorithm: PaletteAlgorithm,
+
+    /// Refine the palette with up to this many rounds of k-means, stopping early once it converges.
+    /// 0 skips refinement.
+    #[arg(long, default_value_t = 0)]
+    pub kmeans_iterations: usize,
+
+    /// Stop k-means once no palette colour moves further than this in a round, measured in the
+    /// colour space the palette is picked in.
+    #[arg(long, value_parser = parse_convergence, default_value_t = 0.5)]
+    pub kmeans_convergence: f64,
+
+    /// The colour space the palette is picked in.
+    #[arg(long, value_enum, default_value_t = ColourSpace::Srgb)]
+    pub colour_space: ColourSpace,
+
     /// How many frames to send to the GPU at a time. Setting it to 0 will use as much memory as your
     /// GPU allows in a storage buffer.
     #[arg(short, long, default_value_t = 0)]
@@ -21,6 +82,221 @@ pub struct Cli {
     #[arg(short, long, default_value_t = 5)]
     pub transparency_threshold: u32,
 
+    /// The input's format. Detected from the path or the input's first few bytes if not given.
+    #[arg(long, value_enum)]
+    pub input_format: Option<InputFormat>,
+
+    /// The size of raw rgb24 input frames, e.g. "640x480". Implies --input-format raw.
+    #[arg(long, value_parser = parse_size, required_if_eq("input_format", "raw"))]
+    pub raw_size: Option<(usize, usize)>,
+
+    /// Frames per second, for inputs that don't time their own frames. Defaults to 10.
+    #[arg(long, value_parser = parse_fps, conflicts_with = "delay")]
+    pub fps: Option<f64>,
+
+    /// Seconds each frame is shown for, for inputs that don't time their own frames.
+    #[arg(long, value_parser = parse_seconds)]
+    pub delay: Option<f64>,
+
+    /// How y4m input's colours were converted to YUV. Guessed from the video's height if not given.
+    #[arg(long, value_enum)]
+    pub yuv_matrix: Option<YuvMatrix>,
+
+    /// What to do if a frame fails to decode partway through the input.
+    #[arg(long, value_enum, default_value_t = DecodeMode::Strict)]
+    pub decode_mode: DecodeMode,
+
+    /// What to do with frames that extend past the input's logical screen.
+    #[arg(long, value_enum, default_value_t = CanvasFit::Clip)]
+    pub canvas_fit: CanvasFit,
+
+    /// Override the input's loop count with how many times to repeat after the first play (0 to play
+    /// once), or "infinite".
+    #[arg(long, value_parser = parse_repeat)]
+    pub loop_count: Option<Repeat>,
+
+    /// Don't copy comments and application extensions (e.g. XMP, ICC profiles) from the input.
+    #[arg(long)]
+    pub strip_metadata: bool,
+
+    /// Only keep frames in this end-exclusive index range, e.g. "10..50", "10.." or "..50".
+    #[arg(long, value_parser = parse_index_range, conflicts_with_all = ["start_time", "end_time"])]
+    pub frames: Option<Range<usize>>,
+
+    /// Drop everything shown before this many seconds into the animation.
+    #[arg(long, value_parser = parse_seconds)]
+    pub start_time: Option<f64>,
+
+    /// Drop everything shown from this many seconds into the animation onwards.
+    #[arg(long, value_parser = parse_seconds)]
+    pub end_time: Option<f64>,
+
+    /// Drop frames so that at most this many are shown per second, adding their delay to the
+    /// previous kept frame. At most 100, since delays are in hundredths of a second.
+    #[arg(long, value_parser = parse_max_fps, conflicts_with = "keep_every")]
+    pub max_fps: Option<f64>,
+
+    /// Only keep every Nth frame, adding the delay of the dropped frames to the previous kept frame.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub keep_every: Option<u64>,
+
+    /// Average dropped frames into the kept frame instead of discarding them.
+    #[arg(long)]
+    pub blend_dropped: bool,
+
+    /// Write every frame as a PNG after each stage (decoding, undithering, quantization and
+    /// transparency optimization, where transparent pixels are magenta) into this directory, along
+    /// with the palette. Stages that are skipped, like undithering true-colour input, aren't written.
+    #[arg(long)]
+    pub dump_stages: Option<PathBuf>,
+
     #[command(flatten)]
     pub verbosity: Verbosity<WarnLevel>,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    Gif,
+    /// Animated or still PNG.
+    Apng,
+    /// A directory or pattern of PNG frames, timed by --fps or --delay.
+    PngSequence,
+    /// Headerless rgb24 frames of --raw-size, timed by --fps or --delay.
+    Raw,
+    /// YUV4MPEG2 video with 8 bit samples.
+    Y4m,
+}
+impl InputFormat {
+    /// for inputs that aren't a single file, so can't be detected from their contents
+    pub fn from_path(path: &str) -> Option<Self> {
+        (Path::new(path).is_dir() || sequence::is_pattern(path)).then_some(InputFormat::PngSequence)
+    }
+    /// falls back to gif, so that anything unrecognised gets the gif decoder's error
+    pub fn detect(signature: &[u8]) -> Self {
+        if signature.starts_with(b"\x89PNG\r\n\x1a\n") {
+            InputFormat::Apng
+        } else if signature.starts_with(b"YUV4MPEG") {
+            InputFormat::Y4m
+        } else {
+            InputFormat::Gif
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Gif,
+    /// Lossless animated WebP.
+    Webp,
+    /// Animated PNG.
+    Apng,
+}
+impl OutputFormat {
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_ref().and_then(|extension| extension.to_str()) {
+            Some("webp") => OutputFormat::Webp,
+            Some("png" | "apng") => OutputFormat::Apng,
+            _ => OutputFormat::Gif,
+        }
+    }
+}
+
+impl Cli {
+    pub fn output_format(&self) -> OutputFormat {
+        self.format
+            .unwrap_or_else(|| OutputFormat::from_path(&self.output))
+    }
+    pub fn frame_range(&self) -> FrameRange {
+        if let Some(frames) = &self.frames {
+            return FrameRange::Index(frames.clone());
+        }
+        if self.start_time.is_none() && self.end_time.is_none() {
+            return FrameRange::default();
+        }
+        let to_centiseconds = |seconds: f64| (seconds * 100.0).round() as u64;
+        FrameRange::Time(
+            self.start_time.map_or(0, to_centiseconds)
+                ..self.end_time.map_or(u64::MAX, to_centiseconds),
+        )
+    }
+    pub fn frame_rate(&self) -> FrameRate {
+        match (self.fps, self.delay) {
+            (Some(fps), _) => FrameRate::from_fps(fps),
+            (None, Some(seconds)) => FrameRate::from_delay(seconds),
+            (None, None) => FrameRate::default(),
+        }
+    }
+    pub fn kmeans_options(&self) -> KmeansOptions {
+        KmeansOptions {
+            max_iterations: self.kmeans_iterations,
+            convergence: self.kmeans_convergence,
+        }
+    }
+    pub fn decimate_rate(&self) -> DecimateRate {
+        match (self.max_fps, self.keep_every) {
+            (Some(fps), _) => DecimateRate::MaxFps(fps),
+            (None, Some(n)) => DecimateRate::KeepEvery(n as usize),
+            (None, None) => DecimateRate::default(),
+        }
+    }
+}
+
+fn parse_repeat(s: &str) -> Result<Repeat, String> {
+    if s == "infinite" {
+        return Ok(Repeat::Infinite);
+    }
+    s.parse().map(Repeat::Finite).map_err(|e| format!("{e}"))
+}
+/// returns (width, height)
+fn parse_size(s: &str) -> Result<(usize, usize), String> {
+    let (width, height) = s.split_once('x').ok_or("expected a size like 640x480")?;
+    let parse_dimension = |dimension: &str| match dimension.parse::<usize>() {
+        Ok(0) => Err("width and height must be positive".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    };
+    Ok((parse_dimension(width)?, parse_dimension(height)?))
+}
+fn parse_index_range(s: &str) -> Result<Range<usize>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or("expected a range like 10..50, 10.. or ..50")?;
+    let parse_bound = |bound: &str, default: usize| {
+        if bound.is_empty() {
+            Ok(default)
+        } else {
+            bound.parse().map_err(|e| format!("{e}"))
+        }
+    };
+    Ok(parse_bound(start, 0)?..parse_bound(end, usize::MAX)?)
+}
+fn parse_seconds(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(seconds) if seconds >= 0.0 => Ok(seconds),
+        Ok(_) => Err("must not be negative".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+fn parse_convergence(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(distance) if distance >= 0.0 => Ok(distance),
+        Ok(_) => Err("must not be negative".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+fn parse_fps(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fps) if fps > 0.0 && fps.is_finite() => Ok(fps),
+        Ok(_) => Err("must be positive".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+fn parse_max_fps(s: &str) -> Result<f64, String> {
+    match parse_fps(s)? {
+        fps if fps <= 100.0 => Ok(fps),
+        _ => Err("must be at most 100, since delays are in hundredths of a second".to_string()),
+    }
+}
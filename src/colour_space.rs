@@ -0,0 +1,96 @@
+use clap::ValueEnum;
+
+use crate::image::Rgb;
+
+/// what the palette algorithms split and average in. colours are converted into it and stored back
+/// in an Rgb, scaled so that distances within it stay proportional
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColourSpace {
+    /// The input's own sRGB values.
+    #[default]
+    Srgb,
+    /// Oklab, where equal distances look about equally different, so similar looking colours get
+    /// merged before ones that stand out.
+    Oklab,
+}
+impl ColourSpace {
+    pub fn encode(self, rgb: Rgb) -> Rgb {
+        match self {
+            ColourSpace::Srgb => rgb,
+            ColourSpace::Oklab => {
+                let [l, a, b] = srgb_to_oklab([rgb.r, rgb.g, rgb.b]);
+                // a and b stay within about ±0.32, so every axis shares one scale and still fits
+                Rgb::new(
+                    to_u8(l * OKLAB_SCALE),
+                    to_u8(a * OKLAB_SCALE + 128.0),
+                    to_u8(b * OKLAB_SCALE + 128.0),
+                )
+            }
+        }
+    }
+    pub fn decode(self, rgb: Rgb) -> Rgb {
+        match self {
+            ColourSpace::Srgb => rgb,
+            ColourSpace::Oklab => {
+                let [r, g, b] = oklab_to_srgb([
+                    rgb.r as f64 / OKLAB_SCALE,
+                    (rgb.g as f64 - 128.0) / OKLAB_SCALE,
+                    (rgb.b as f64 - 128.0) / OKLAB_SCALE,
+                ]);
+                Rgb::new(r, g, b)
+            }
+        }
+    }
+    /// per channel weights for squared distances between encoded colours. srgb's are weighted by
+    /// luma to match the quantizer
+    pub fn weights(self) -> [f64; 3] {
+        match self {
+            ColourSpace::Srgb => [0.299, 0.587, 0.114],
+            ColourSpace::Oklab => [1.0; 3],
+        }
+    }
+}
+
+const OKLAB_SCALE: f64 = 255.0;
+
+/// https://bottosson.github.io/posts/oklab/
+fn srgb_to_oklab(rgb: [u8; 3]) -> [f64; 3] {
+    let [r, g, b] = rgb.map(|x| to_linear(x as f64 / 255.0));
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+fn oklab_to_srgb([l, a, b]: [f64; 3]) -> [u8; 3] {
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+        -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+        -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+    ]
+    .map(|x| to_u8(from_linear(x.clamp(0.0, 1.0)) * 255.0))
+}
+fn to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+fn from_linear(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+/// float to int casts saturate
+fn to_u8(x: f64) -> u8 {
+    x.round() as u8
+}
@@ -0,0 +1,143 @@
+use crate::{
+    error::Result,
+    image::{GifFrame, Rgba},
+};
+
+/// how many frames to drop
+#[derive(Debug, Clone, Copy)]
+pub enum DecimateRate {
+    /// keep one frame out of every n
+    KeepEvery(usize),
+    /// keep at most this many frames per second
+    MaxFps(f64),
+}
+impl Default for DecimateRate {
+    fn default() -> Self {
+        DecimateRate::KeepEvery(1)
+    }
+}
+
+/// the kept frame, plus what's needed to blend in the frames that get folded into it
+struct Group {
+    frame: GifFrame,
+    size: usize,
+    /// per pixel, delay-weighted sums of r, g, b, and the total weight of the opaque samples
+    sums: Option<Vec<[u64; 4]>>,
+}
+impl Group {
+    fn new(frame: GifFrame, blend: bool) -> Self {
+        let mut sums = blend.then(|| vec![[0; 4]; frame.image.buffer.len()]);
+        if let Some(sums) = &mut sums {
+            accumulate(sums, &frame);
+        }
+        Self {
+            frame,
+            size: 1,
+            sums,
+        }
+    }
+    fn add(&mut self, frame: &GifFrame) {
+        self.size += 1;
+        self.frame.delay = self.frame.delay.saturating_add(frame.delay);
+        if let Some(sums) = &mut self.sums {
+            accumulate(sums, frame);
+        }
+    }
+    fn finish(mut self) -> GifFrame {
+        if let Some(sums) = &self.sums
+            && self.size > 1
+        {
+            for (pixel, [r, g, b, weight]) in self.frame.image.buffer.iter_mut().zip(sums) {
+                *pixel = if *weight == 0 {
+                    Rgba::TRANSPARENT
+                } else {
+                    Rgba::new(
+                        (r / weight) as u8,
+                        (g / weight) as u8,
+                        (b / weight) as u8,
+                        255,
+                    )
+                };
+            }
+        }
+        self.frame
+    }
+}
+
+fn accumulate(sums: &mut [[u64; 4]], frame: &GifFrame) {
+    // zero delay frames are still briefly shown
+    let weight = frame.delay.max(1) as u64;
+    for (sum, pixel) in sums.iter_mut().zip(&frame.image.buffer) {
+        if pixel.is_transparent() {
+            continue;
+        }
+        sum[0] += weight * pixel.r as u64;
+        sum[1] += weight * pixel.g as u64;
+        sum[2] += weight * pixel.b as u64;
+        sum[3] += weight;
+    }
+}
+
+/// drops frames and adds their delay to the last kept frame so the total duration stays the same,
+/// optionally averaging the dropped frames into it
+pub struct Decimate<I: Iterator<Item = Result<GifFrame>>> {
+    frames: I,
+    rate: DecimateRate,
+    blend: bool,
+    pending: Option<Group>,
+    elapsed: u64,
+    /// centiseconds, when decimating by fps
+    next_slot: f64,
+}
+impl<I: Iterator<Item = Result<GifFrame>>> Decimate<I> {
+    pub fn new(frames: I, rate: DecimateRate, blend: bool) -> Self {
+        Self {
+            frames,
+            rate,
+            blend,
+            pending: None,
+            elapsed: 0,
+            next_slot: 0.0,
+        }
+    }
+    /// given the size of the current group, if there is one
+    fn starts_group(&mut self, group_size: Option<usize>) -> bool {
+        match self.rate {
+            DecimateRate::KeepEvery(n) => group_size.is_none_or(|size| size >= n),
+            DecimateRate::MaxFps(fps) => {
+                let shown_at = self.elapsed as f64;
+                if group_size.is_some() && shown_at < self.next_slot {
+                    return false;
+                }
+                let interval = 100.0 / fps;
+                self.next_slot = ((shown_at / interval).floor() + 1.0) * interval;
+                true
+            }
+        }
+    }
+}
+impl<I: Iterator<Item = Result<GifFrame>>> Iterator for Decimate<I> {
+    type Item = Result<GifFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = match self.frames.next() {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return self.pending.take().map(|group| Ok(group.finish())),
+            };
+            let delay = frame.delay as u64;
+            let group_size = self.pending.as_ref().map(|group| group.size);
+            let finished = if self.starts_group(group_size) {
+                self.pending.replace(Group::new(frame, self.blend))
+            } else {
+                self.pending.as_mut().unwrap().add(&frame);
+                None
+            };
+            self.elapsed += delay;
+            if let Some(group) = finished {
+                return Some(Ok(group.finish()));
+            }
+        }
+    }
+}
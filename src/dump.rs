@@ -0,0 +1,146 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use png::{BitDepth, ColorType, Encoder};
+
+use crate::{
+    error::Result,
+    image::{GifFrame, Image, Rgb},
+    transparency::TransparencyOutput,
+};
+
+/// pixels the transparency optimizer made transparent are drawn in this colour
+const MASK_COLOUR: [u8; 4] = [255, 0, 255, 255];
+/// each palette entry is a square this big in the swatch
+const SWATCH_SIZE: usize = 16;
+const SWATCH_COLUMNS: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Read,
+    Undither,
+    Quantize,
+    Transparency,
+}
+impl Stage {
+    /// numbered so that the directories sort in pipeline order
+    fn dir_name(self) -> &'static str {
+        match self {
+            Stage::Read => "1-read",
+            Stage::Undither => "2-undither",
+            Stage::Quantize => "3-quantize",
+            Stage::Transparency => "4-transparency",
+        }
+    }
+}
+
+/// writes what each stage of the pipeline produced as pngs, to find which one went wrong
+pub struct StageDump {
+    dir: PathBuf,
+}
+impl StageDump {
+    /// only the stages that run get a directory, so that a skipped one isn't mistaken for a no-op
+    pub fn new(dir: &Path, stages: &[Stage]) -> Result<Self> {
+        for stage in stages {
+            fs::create_dir_all(dir.join(stage.dir_name()))?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+    pub fn write_frame(&self, stage: Stage, index: usize, image: &Image) -> Result<()> {
+        let rgba: Vec<u8> = image
+            .buffer
+            .iter()
+            .flat_map(|pixel| [pixel.r, pixel.g, pixel.b, pixel.a])
+            .collect();
+        write_png(
+            &self.frame_path(stage, index),
+            image.height,
+            image.width,
+            &rgba,
+        )
+    }
+    /// for a chunk of frames, given the index of its first frame
+    pub fn write_frames(
+        &self,
+        stage: Stage,
+        first_index: usize,
+        frames: &[GifFrame],
+    ) -> Result<()> {
+        for (i, frame) in frames.iter().enumerate() {
+            self.write_frame(stage, first_index + i, &frame.image)?;
+        }
+        Ok(())
+    }
+    /// the frame as it will be drawn over the previous one, with its transparent pixels visible
+    pub fn write_transparency(&self, index: usize, output: &TransparencyOutput) -> Result<()> {
+        let (frame, transparent_pixels, _) = output;
+        let rgba: Vec<u8> = frame
+            .image
+            .buffer
+            .iter()
+            .zip(transparent_pixels)
+            .flat_map(|(pixel, &transparent)| {
+                if transparent {
+                    MASK_COLOUR
+                } else {
+                    [pixel.r, pixel.g, pixel.b, 255]
+                }
+            })
+            .collect();
+        let path = self.frame_path(Stage::Transparency, index);
+        write_png(&path, frame.image.height, frame.image.width, &rgba)
+    }
+    /// palette.png, or with local palettes, one numbered by the group of frames that used it
+    pub fn write_palettes(&self, palettes: &[Vec<Rgb>]) -> Result<()> {
+        if let [palette] = palettes {
+            return write_palette(&self.dir.join("palette.png"), palette);
+        }
+        for (group, palette) in palettes.iter().enumerate() {
+            write_palette(&self.dir.join(format!("palette-{group:05}.png")), palette)?;
+        }
+        Ok(())
+    }
+    fn frame_path(&self, stage: Stage, index: usize) -> PathBuf {
+        self.dir
+            .join(stage.dir_name())
+            .join(format!("{index:05}.png"))
+    }
+}
+
+/// one square per entry, in palette order
+fn write_palette(path: &Path, palette: &[Rgb]) -> Result<()> {
+    let columns = SWATCH_COLUMNS.min(palette.len()).max(1);
+    let rows = palette.len().div_ceil(columns).max(1);
+    let width = columns * SWATCH_SIZE;
+    let height = rows * SWATCH_SIZE;
+    let mut rgba = vec![0; 4 * height * width];
+    for (index, colour) in palette.iter().enumerate() {
+        let top = index / columns * SWATCH_SIZE;
+        let left = index % columns * SWATCH_SIZE;
+        for i in top..top + SWATCH_SIZE {
+            for j in left..left + SWATCH_SIZE {
+                let pixel = 4 * (i * width + j);
+                rgba[pixel..pixel + 4].copy_from_slice(&[colour.r, colour.g, colour.b, 255]);
+            }
+        }
+    }
+    write_png(path, height, width, &rgba)
+}
+fn write_png(path: &Path, height: usize, width: usize, rgba: &[u8]) -> Result<()> {
+    let mut encoder = Encoder::new(
+        BufWriter::new(File::create(path)?),
+        width as u32,
+        height as u32,
+    );
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+    Ok(())
+}
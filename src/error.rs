@@ -0,0 +1,98 @@
+use std::{fmt, io};
+
+use gif::{DecodingError, EncodingError};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    MalformedGif(String),
+    MissingPalette,
+    MalformedPng(String),
+    MalformedY4m(String),
+    InvalidInput(String),
+    FrameDecode { index: usize, cause: Box<Error> },
+    GpuUnavailable(String),
+    Encode(EncodingError),
+    WebpEncode(image_webp::EncodingError),
+    PngEncode(png::EncodingError),
+    NoFrames,
+}
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {e}"),
+            Error::MalformedGif(reason) => write!(f, "malformed gif: {reason}"),
+            Error::MissingPalette => write!(f, "malformed gif: no global or local palette"),
+            Error::MalformedPng(reason) => write!(f, "malformed png: {reason}"),
+            Error::MalformedY4m(reason) => write!(f, "malformed y4m: {reason}"),
+            Error::InvalidInput(reason) => write!(f, "invalid input: {reason}"),
+            Error::FrameDecode { index, cause } => {
+                write!(f, "failed to decode frame {index}: {cause}")
+            }
+            Error::GpuUnavailable(reason) => write!(f, "no usable GPU: {reason}"),
+            Error::Encode(e) => write!(f, "failed to encode output: {e}"),
+            Error::WebpEncode(e) => write!(f, "failed to encode output: {e}"),
+            Error::PngEncode(e) => write!(f, "failed to encode output: {e}"),
+            Error::NoFrames => write!(f, "no frames left to write"),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Encode(e) => Some(e),
+            Error::WebpEncode(e) => Some(e),
+            Error::PngEncode(e) => Some(e),
+            Error::FrameDecode { cause, .. } => Some(cause),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+impl From<DecodingError> for Error {
+    fn from(e: DecodingError) -> Self {
+        match e {
+            DecodingError::Io(e) => Error::Io(e),
+            e => Error::MalformedGif(e.to_string()),
+        }
+    }
+}
+impl From<EncodingError> for Error {
+    fn from(e: EncodingError) -> Self {
+        match e {
+            EncodingError::Io(e) => Error::Io(e),
+            e => Error::Encode(e),
+        }
+    }
+}
+impl From<png::DecodingError> for Error {
+    fn from(e: png::DecodingError) -> Self {
+        match e {
+            png::DecodingError::IoError(e) => Error::Io(e),
+            e => Error::MalformedPng(e.to_string()),
+        }
+    }
+}
+impl From<image_webp::EncodingError> for Error {
+    fn from(e: image_webp::EncodingError) -> Self {
+        match e {
+            image_webp::EncodingError::IoError(e) => Error::Io(e),
+            e => Error::WebpEncode(e),
+        }
+    }
+}
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        match e {
+            png::EncodingError::IoError(e) => Error::Io(e),
+            e => Error::PngEncode(e),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+/// a constant rate for inputs that don't time their own frames, spread over whole-centisecond
+/// delays without the rounding drifting over the animation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRate {
+    /// centiseconds per frame
+    interval: f64,
+}
+impl FrameRate {
+    pub fn from_fps(fps: f64) -> Self {
+        Self {
+            interval: 100.0 / fps,
+        }
+    }
+    pub fn from_delay(seconds: f64) -> Self {
+        Self {
+            interval: seconds * 100.0,
+        }
+    }
+    /// the frame at index n is always shown at round(n * interval)
+    pub fn delay(&self, index: usize) -> u16 {
+        let shown_at = (index as f64 * self.interval).round();
+        let hidden_at = ((index + 1) as f64 * self.interval).round();
+        (hidden_at - shown_at).min(u16::MAX as f64) as u16
+    }
+}
+impl Default for FrameRate {
+    fn default() -> Self {
+        Self::from_fps(10.0)
+    }
+}
@@ -3,19 +3,22 @@ use std::sync::mpsc::channel;
 use bytemuck::{Pod, Zeroable};
 use log::{info, warn};
 use wgpu::{
-    BindGroupDescriptor, BindGroupEntry, BufferDescriptor, BufferUsages, ComputePassDescriptor,
-    ComputePassTimestampWrites, ComputePipelineDescriptor, DeviceDescriptor, Features, Limits,
-    PollType, QuerySetDescriptor, RequestAdapterOptions,
+    Adapter, BindGroupDescriptor, BindGroupEntry, BufferDescriptor, BufferUsages,
+    ComputePassDescriptor, ComputePassTimestampWrites, ComputePipelineDescriptor, DeviceDescriptor,
+    Features, Limits, PollType, QuerySetDescriptor, RequestAdapterOptions,
     util::{BufferInitDescriptor, DeviceExt, DownloadBuffer},
 };
 
-use crate::image::{Image, Rgb};
+use crate::{
+    error::{Error, Result},
+    image::{Image, Rgb, Rgba},
+};
 
 pub fn run_shader_with_frames(
     entry_point: &str,
     frames: Vec<&Image>,
     palettes: Vec<&Vec<Rgb>>,
-) -> Vec<Image> {
+) -> Result<Vec<Image>> {
     pollster::block_on(run_shader_with_frames_async(entry_point, frames, palettes))
 }
 #[derive(Pod, Zeroable, Clone, Copy)]
@@ -46,13 +49,32 @@ impl RgbGpu {
     }
 }
 
+#[derive(Pod, Zeroable, Clone, Copy)]
+#[repr(C)]
+struct RgbaGpu {
+    r: u32,
+    g: u32,
+    b: u32,
+    a: u32,
+}
+impl RgbaGpu {
+    pub fn from_rgba(rgba: Rgba) -> Self {
+        Self {
+            r: rgba.r as u32,
+            g: rgba.g as u32,
+            b: rgba.b as u32,
+            a: rgba.a as u32,
+        }
+    }
+}
+
 async fn run_shader_with_frames_async(
     entry_point: &str,
     frames: Vec<&Image>,
     palettes: Vec<&Vec<Rgb>>,
-) -> Vec<Image> {
+) -> Result<Vec<Image>> {
     if frames.is_empty() {
-        return frames.into_iter().cloned().collect();
+        return Ok(Vec::new());
     }
     let num_frames = frames.len();
     if palettes.len() != num_frames {
@@ -64,11 +86,7 @@ async fn run_shader_with_frames_async(
     }
     let height = frames.first().unwrap().height;
     let width = frames.first().unwrap().width;
-    let instance = wgpu::Instance::default();
-    let adapter = instance
-        .request_adapter(&RequestAdapterOptions::default())
-        .await
-        .unwrap();
+    let adapter = request_adapter().await?;
     let adapter_limits = adapter.limits();
     let supports_timestamp_queries = adapter.features().contains(Features::TIMESTAMP_QUERY);
     if !supports_timestamp_queries {
@@ -89,7 +107,7 @@ async fn run_shader_with_frames_async(
             ..Default::default()
         })
         .await
-        .unwrap();
+        .map_err(|e| Error::GpuUnavailable(e.to_string()))?;
 
     let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
 
@@ -131,10 +149,10 @@ async fn run_shader_with_frames_async(
         usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
     });
 
-    let frames_input: Vec<RgbGpu> = frames
+    let frames_input: Vec<RgbaGpu> = frames
         .iter()
         .flat_map(|img| img.buffer.clone())
-        .map(RgbGpu::from_rgb)
+        .map(RgbaGpu::from_rgba)
         .collect();
 
     let input_buffer = device.create_buffer_init(&BufferInitDescriptor {
@@ -222,30 +240,41 @@ async fn run_shader_with_frames_async(
     queue.submit(Some(encoder.finish()));
     let (tx, rx) = channel();
     DownloadBuffer::read_buffer(&device, &queue, &output_buffer.slice(..), move |result| {
-        tx.send(result.unwrap().to_vec()).unwrap()
+        tx.send(result.map(|buffer| buffer.to_vec())).unwrap()
     });
     let (timestamp_tx, timestamp_rx) = channel();
     if supports_timestamp_queries {
         DownloadBuffer::read_buffer(&device, &queue, &query_buffer.slice(..), move |result| {
-            timestamp_tx.send(result.unwrap().to_vec()).unwrap()
+            timestamp_tx
+                .send(result.map(|buffer| buffer.to_vec()))
+                .unwrap()
         });
     }
-    device.poll(PollType::wait_indefinitely()).unwrap();
+    device
+        .poll(PollType::wait_indefinitely())
+        .map_err(|e| Error::GpuUnavailable(e.to_string()))?;
     if supports_timestamp_queries {
-        let bytes = timestamp_rx.recv().unwrap();
-        let start_end_timestamps: &[u64] = bytemuck::cast_slice(&bytes);
-        let elapsed_ms = (start_end_timestamps[1] - start_end_timestamps[0]) as f64
-            * queue.get_timestamp_period() as f64
-            / 1_000_000.0;
-        info!("GPU {entry_point} compute took {elapsed_ms:.1} ms");
+        // the timing is only logged, so failing to read it isn't worth failing the run over
+        match timestamp_rx.recv().unwrap() {
+            Ok(bytes) => {
+                let start_end_timestamps: &[u64] = bytemuck::cast_slice(&bytes);
+                let elapsed_ms = (start_end_timestamps[1] - start_end_timestamps[0]) as f64
+                    * queue.get_timestamp_period() as f64
+                    / 1_000_000.0;
+                info!("GPU {entry_point} compute took {elapsed_ms:.1} ms");
+            }
+            Err(e) => warn!("couldn't read the GPU {entry_point} compute timestamps: {e}"),
+        }
     }
-    let bytes = rx.recv().unwrap();
-    bytes
+    let bytes = rx
+        .recv()
+        .unwrap()
+        .map_err(|e| Error::GpuUnavailable(e.to_string()))?;
+    Ok(bytes
         .chunks_exact(bytes.len() / num_frames)
         .map(|frame_bytes| {
-            let rgbs = frame_bytes.chunks_exact(4 * 4).map(|rgb_bytes| {
-                Rgb::new(rgb_bytes[0], rgb_bytes[4], rgb_bytes[8]) //little endian
-                //remember 12..16 is _padding
+            let rgbs = frame_bytes.chunks_exact(4 * 4).map(|rgba_bytes| {
+                Rgba::new(rgba_bytes[0], rgba_bytes[4], rgba_bytes[8], rgba_bytes[12]) //little endian
             });
             assert_eq!(rgbs.len(), height * width);
 
@@ -255,22 +284,25 @@ async fn run_shader_with_frames_async(
                 width,
             }
         })
-        .collect()
+        .collect())
 }
-pub fn get_highest_chunk_size(height: usize, width: usize) -> usize {
-    let highest_buffer_size = pollster::block_on(get_highest_buffer_size());
-    let frame_size = size_of::<RgbGpu>() * height * width + size_of::<Image>();
-    highest_buffer_size / frame_size
+pub fn get_highest_chunk_size(height: usize, width: usize) -> Result<usize> {
+    let highest_buffer_size = pollster::block_on(get_highest_buffer_size())?;
+    let frame_size = size_of::<RgbaGpu>() * height * width + size_of::<Image>();
+    Ok(highest_buffer_size / frame_size)
 }
-async fn get_highest_buffer_size() -> usize {
-    let instance = wgpu::Instance::default();
-    let adapter = instance
-        .request_adapter(&RequestAdapterOptions::default())
-        .await
-        .unwrap();
+async fn get_highest_buffer_size() -> Result<usize> {
+    let adapter = request_adapter().await?;
     let adapter_limits = adapter.limits();
-    adapter_limits
+    Ok(adapter_limits
         .max_storage_buffer_binding_size
         .min(adapter_limits.max_buffer_size)
-        .min(usize::MAX as u64) as usize
+        .min(usize::MAX as u64) as usize)
+}
+async fn request_adapter() -> Result<Adapter> {
+    let instance = wgpu::Instance::default();
+    instance
+        .request_adapter(&RequestAdapterOptions::default())
+        .await
+        .map_err(|e| Error::GpuUnavailable(e.to_string()))
 }
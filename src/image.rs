@@ -2,6 +2,7 @@ use std::hash::Hash;
 use std::{cmp::Ordering, hash::Hasher};
 
 use bitcode::{Decode, Encode};
+use gif::DisposalMethod;
 
 #[derive(Debug, Clone, Copy, Default, Decode, Encode)]
 pub struct Rgb {
@@ -60,28 +61,92 @@ impl Rgb {
         (0.299 * dr * dr + 0.587 * dg * dg + 0.114 * db * db) as u32
     }
 }
+/// a pixel that is either fully transparent (a == 0) or opaque, since that's all a gif can represent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Decode, Encode)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+impl Rgba {
+    /// transparent pixels always have this value so that they compare and hash equal
+    pub const TRANSPARENT: Rgba = Rgba {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 0,
+    };
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        if a == 0 {
+            Self::TRANSPARENT
+        } else {
+            Self { r, g, b, a: 255 }
+        }
+    }
+    pub fn opaque(rgb: Rgb) -> Self {
+        Self::new(rgb.r, rgb.g, rgb.b, 255)
+    }
+    pub fn is_transparent(&self) -> bool {
+        self.a == 0
+    }
+    pub fn rgb(&self) -> Rgb {
+        Rgb::new(self.r, self.g, self.b)
+    }
+}
 #[derive(Clone, Debug, Decode, Encode)]
 pub struct Image {
-    pub buffer: Vec<Rgb>,
+    pub buffer: Vec<Rgba>,
     pub height: usize,
     pub width: usize,
 }
 impl Image {
+    /// fully transparent
     pub fn blank(height: usize, width: usize) -> Self {
         Self {
-            buffer: vec![Rgb::default(); height * width],
+            buffer: vec![Rgba::TRANSPARENT; height * width],
             height,
             width,
         }
     }
-    pub fn get(&self, i: usize, j: usize) -> Rgb {
+    pub fn get(&self, i: usize, j: usize) -> Rgba {
         self.buffer[self.width * i + j]
     }
-    pub fn get_mut(&mut self, i: usize, j: usize) -> &mut Rgb {
+    pub fn get_mut(&mut self, i: usize, j: usize) -> &mut Rgba {
         &mut self.buffer[self.width * i + j]
     }
 }
 
+/// mirrors gif::DisposalMethod so that it can be encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Decode, Encode)]
+pub enum Disposal {
+    #[default]
+    Any,
+    Keep,
+    Background,
+    Previous,
+}
+impl From<DisposalMethod> for Disposal {
+    fn from(dispose: DisposalMethod) -> Self {
+        match dispose {
+            DisposalMethod::Any => Disposal::Any,
+            DisposalMethod::Keep => Disposal::Keep,
+            DisposalMethod::Background => Disposal::Background,
+            DisposalMethod::Previous => Disposal::Previous,
+        }
+    }
+}
+impl From<Disposal> for DisposalMethod {
+    fn from(dispose: Disposal) -> Self {
+        match dispose {
+            Disposal::Any => DisposalMethod::Any,
+            Disposal::Keep => DisposalMethod::Keep,
+            Disposal::Background => DisposalMethod::Background,
+            Disposal::Previous => DisposalMethod::Previous,
+        }
+    }
+}
+
 #[derive(Clone, Decode, Encode)]
 pub struct GifFrame {
     pub image: Image,
@@ -91,6 +156,11 @@ pub struct GifFrame {
     pub left: usize,
     pub local_height: usize,
     pub local_width: usize,
+    /// the rest are as they were in the source frame, before compositing
+    pub dispose: Disposal,
+    pub interlaced: bool,
+    pub needs_user_input: bool,
+    pub transparent_index: Option<u8>,
 }
 impl GifFrame {
     pub fn new(image: Image, palette: Vec<Rgb>, delay: u16) -> Self {
@@ -102,6 +172,10 @@ impl GifFrame {
             image,
             palette,
             delay,
+            dispose: Disposal::default(),
+            interlaced: false,
+            needs_user_input: false,
+            transparent_index: None,
         }
     }
 }
@@ -1,10 +1,24 @@
+pub mod apng;
 pub mod chunked_file;
 pub mod chunked_iter;
+pub mod colour_space;
+pub mod decimate;
+pub mod dump;
+pub mod error;
+pub mod frame_rate;
 pub mod gpu;
 pub mod image;
+pub mod metadata;
+pub mod octree;
 pub mod palette;
 pub mod quantizer;
+pub mod raw;
 pub mod reader;
+pub mod sequence;
+pub mod source;
 pub mod transparency;
+pub mod trim;
 pub mod undither;
+pub mod webp;
 pub mod writer;
+pub mod y4m;
@@ -1,62 +1,317 @@
 use clap::Parser;
+use gif_compressor::apng::{ApngReader, ApngWriter};
 use gif_compressor::chunked_file::ChunkedFile;
 use gif_compressor::chunked_iter::ChunkedIter;
+use gif_compressor::decimate::Decimate;
+use gif_compressor::dump::{Stage, StageDump};
+use gif_compressor::error::{Error, Result};
+use gif_compressor::image::{GifFrame, Rgb};
+use gif_compressor::raw::RawReader;
 use gif_compressor::reader::GifReader;
+use gif_compressor::sequence::PngSequence;
+use gif_compressor::source::FrameSource;
 use gif_compressor::transparency::TransparencyOptimizer;
+use gif_compressor::trim::Trim;
+use gif_compressor::webp::WebpWriter;
 use gif_compressor::writer::GifWriter;
+use gif_compressor::y4m::Y4mReader;
 use gif_compressor::{gpu, quantizer};
 use gif_compressor::{palette, undither};
-use log::info;
+use log::{info, warn};
 use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::iter;
+use std::process::ExitCode;
 use std::time::Instant;
 
-use crate::cli::Cli;
+use crate::cli::{Cli, InputFormat, OutputFormat};
 
 mod cli;
 
-fn main() {
+fn main() -> ExitCode {
     let start = Instant::now();
-    let mut cli = Cli::parse();
+    let cli = Cli::parse();
     env_logger::Builder::new()
         .filter_level(cli.verbosity.log_level_filter())
         .init();
-    let reader = GifReader::new(cli.input);
+    if let Err(e) = run(cli) {
+        eprintln!("error: {e}");
+        return ExitCode::FAILURE;
+    }
+    info!(
+        "finished in {:.1}s",
+        start.elapsed().as_millis() as f32 / 1000.0
+    );
+    ExitCode::SUCCESS
+}
+
+fn run(mut cli: Cli) -> Result<()> {
+    let mut reader = open_source(&cli)?;
     let height = reader.height();
     let width = reader.width();
     if cli.chunk_size == 0 {
-        cli.chunk_size = gpu::get_highest_chunk_size(height, width);
+        cli.chunk_size = gpu::get_highest_chunk_size(height, width)?;
         info!("inferring chunk_size = {}", cli.chunk_size);
     }
+    let output_format = cli.output_format();
+    let quantize = !cli.no_quantize || output_format == OutputFormat::Gif;
+    if !quantize {
+        info!("skipping quantization");
+    } else if cli.no_quantize {
+        warn!("gif output is limited to 256 colours, quantizing anyway");
+    }
+    // true-colour frames were never dithered, and have no palette to undither against
+    let undither = reader.is_paletted();
+    if !undither {
+        info!("input is true-colour, skipping undithering");
+    }
+
+    let dump = cli
+        .dump_stages
+        .as_deref()
+        .map(|dir| {
+            let stages = [
+                (Stage::Read, true),
+                (Stage::Undither, undither),
+                (Stage::Quantize, quantize),
+                (Stage::Transparency, true),
+            ];
+            let stages: Vec<Stage> = stages
+                .into_iter()
+                .filter_map(|(stage, runs)| runs.then_some(stage))
+                .collect();
+            StageDump::new(dir, &stages)
+        })
+        .transpose()?;
 
-    let undithered_chunks = ChunkedIter::new(reader, cli.chunk_size).map(undither::undither_chunk);
-    let mut temp_file = tempfile::tempfile().unwrap();
+    let mut temp_file = tempfile::tempfile()?;
     let mut chunked_file = ChunkedFile::new(&mut temp_file);
-    let palette = palette::gen_palette(
-        undithered_chunks.inspect(|chunk| chunked_file.write_chunk(chunk.clone())),
-        height,
-        width,
-    );
-    chunked_file.finish_writing();
+    let mut first_error = None;
+    let mut frame_count = 0;
+    let frames = Trim::new(reader.by_ref(), cli.frame_range());
+    let frames = Decimate::new(frames, cli.decimate_rate(), cli.blend_dropped);
+    // dumped after trimming and decimating, so that every stage numbers the same frame the same
+    let frames = frames.enumerate().map(|(index, frame)| {
+        let frame = frame?;
+        if let Some(dump) = &dump {
+            dump.write_frame(Stage::Read, index, &frame.image)?;
+        }
+        Ok(frame)
+    });
+    let mut undithered_chunks = ChunkedIter::new(frames, cli.chunk_size)
+        .map(|chunk| chunk.into_iter().collect::<Result<Vec<_>>>())
+        .map(|chunk| {
+            if undither {
+                chunk.and_then(undither::undither_chunk)
+            } else {
+                chunk
+            }
+        })
+        .map(|chunk| {
+            let chunk = chunk?;
+            // a skipped stage would only dump copies of the previous one's frames
+            if undither && let Some(dump) = &dump {
+                dump.write_frames(Stage::Undither, frame_count, &chunk)?;
+            }
+            chunked_file.write_chunk(&chunk)?;
+            frame_count += chunk.len();
+            Ok(chunk)
+        })
+        .map_while(|chunk| stop_on_error(chunk, &mut first_error));
+    let gen_palette = |chunks: &mut dyn Iterator<Item = Vec<GifFrame>>| {
+        palette::gen_palette(
+            chunks,
+            height,
+            width,
+            // one index is kept for transparency
+            usize::from(cli.colours) - 1,
+            cli.palette_algorithm,
+            cli.kmeans_options(),
+            cli.colour_space,
+        )
+    };
+    // frames in the same group share a palette, and without local palettes there's one group
+    let group_size = cli.local_palettes.map_or(usize::MAX, |n| n as usize);
+    let palettes: Option<Vec<Vec<Rgb>>> = if !quantize {
+        undithered_chunks.for_each(drop);
+        None
+    } else if cli.local_palettes.is_some() {
+        Some(
+            ChunkedIter::new(undithered_chunks.flatten(), group_size)
+                .map(|group| gen_palette(&mut iter::once(group)))
+                .collect(),
+        )
+    } else {
+        Some(vec![gen_palette(&mut undithered_chunks)])
+    };
+    if let Some(e) = first_error {
+        return Err(e);
+    }
+    if let (Some(dump), Some(palettes)) = (&dump, &palettes) {
+        dump.write_palettes(palettes)?;
+    }
+    if frame_count == 0 {
+        return Err(Error::NoFrames);
+    }
+    chunked_file.finish_writing()?;
+    let mut metadata = reader.metadata();
+    if let Some(repeat) = cli.loop_count {
+        metadata.repeat = repeat;
+    }
+    if cli.strip_metadata {
+        metadata.extensions.clear();
+    }
     info!(
         "saved {:.1} MB of undithered chunks to temp file",
-        chunked_file.size() as f64 / 1_000_000.0
+        chunked_file.size()? as f64 / 1_000_000.0
     );
 
-    let quantized_frames =
-        chunked_file.flat_map(|chunk| quantizer::quantize_chunk(chunk, &palette));
+    let mut first_error = None;
+    let mut quantized_count = 0;
+    let mut quantize_start = 0;
+    let quantized_frames = chunked_file
+        .map(|chunk| match &palettes {
+            Some(palettes) => chunk.and_then(|chunk| {
+                let frame_palettes = (quantize_start..quantize_start + chunk.len())
+                    .map(|index| &palettes[index / group_size])
+                    .collect();
+                quantize_start += chunk.len();
+                quantizer::quantize_chunk(chunk, frame_palettes)
+            }),
+            None => chunk,
+        })
+        .map(|chunk| {
+            let chunk = chunk?;
+            if quantize && let Some(dump) = &dump {
+                dump.write_frames(Stage::Quantize, quantized_count, &chunk)?;
+            }
+            quantized_count += chunk.len();
+            Ok(chunk)
+        })
+        .map_while(|chunk| stop_on_error(chunk, &mut first_error))
+        .flatten();
     let mut transparency = TransparencyOptimizer::new(cli.transparency_threshold);
-    let transparency_optimized = transparency.apply_transparency_all(quantized_frames);
-    let mut output_file = File::create(&cli.output).unwrap();
-    let mut writer = GifWriter::new(
-        transparency_optimized,
-        palette.clone(),
-        height,
-        width,
-        &mut output_file,
-    );
-    while writer.write_frame() {}
-    info!(
-        "finished in {:.1}s",
-        start.elapsed().as_millis() as f32 / 1000.0
-    );
+    let mut dump_error = None;
+    let transparency_optimized = transparency
+        .apply_transparency_all(quantized_frames)
+        .enumerate()
+        .map(|(index, output)| {
+            if let Some(dump) = &dump {
+                dump.write_transparency(index, &output)?;
+            }
+            Ok(output)
+        })
+        .map_while(|output| stop_on_error(output, &mut dump_error));
+    let output = create_output(&cli.output)?;
+    let finished = match output_format {
+        OutputFormat::Gif => {
+            let mut writer = GifWriter::new(
+                transparency_optimized,
+                palettes.as_ref().expect("gif output is always quantized")[0].clone(),
+                cli.local_palettes.is_some(),
+                height,
+                width,
+                &metadata,
+                output,
+            )?;
+            while writer.write_frame()? {}
+            writer.finish()
+        }
+        OutputFormat::Webp => {
+            let mut writer =
+                WebpWriter::new(transparency_optimized, height, width, &metadata, output)?;
+            while writer.write_frame()? {}
+            writer.finish()
+        }
+        OutputFormat::Apng => {
+            // apng only has one palette, so frames with their own are written as rgba
+            let palette = match (&palettes, cli.local_palettes) {
+                (Some(palettes), None) => Some(palettes[0].clone()),
+                _ => None,
+            };
+            let mut writer = ApngWriter::new(
+                transparency_optimized,
+                palette,
+                frame_count,
+                height,
+                width,
+                &metadata,
+                output,
+            )?;
+            while writer.write_frame()? {}
+            writer.finish()
+        }
+    };
+    // a writer that was cut short by an earlier error can fail to finish because of it, so the
+    // earlier error is the one to report
+    if let Some(e) = first_error.or(dump_error) {
+        return Err(e);
+    }
+    finished
+}
+
+fn open_source(cli: &Cli) -> Result<Box<dyn FrameSource>> {
+    let format = cli
+        .input_format
+        .or_else(|| cli.raw_size.map(|_| InputFormat::Raw))
+        .or_else(|| InputFormat::from_path(&cli.input));
+    if format == Some(InputFormat::PngSequence) {
+        let sequence = PngSequence::new(&cli.input, cli.frame_rate(), cli.decode_mode)?;
+        return Ok(Box::new(sequence));
+    }
+    let mut input = open_input(&cli.input)?;
+    let format = match format {
+        Some(format) => format,
+        None => {
+            // peek at the signature, then put it back in front of the rest of the input
+            let mut signature = Vec::new();
+            input.by_ref().take(8).read_to_end(&mut signature)?;
+            let format = InputFormat::detect(&signature);
+            input = Box::new(Cursor::new(signature).chain(input));
+            format
+        }
+    };
+    Ok(match format {
+        InputFormat::Gif => Box::new(GifReader::new(input, cli.decode_mode, cli.canvas_fit)?),
+        InputFormat::Apng => Box::new(ApngReader::new(input, cli.decode_mode)?),
+        InputFormat::Raw => {
+            // clap requires --raw-size when the format is raw
+            let (width, height) = cli.raw_size.unwrap();
+            let reader = RawReader::new(input, height, width, cli.frame_rate(), cli.decode_mode)?;
+            Box::new(reader)
+        }
+        InputFormat::Y4m => Box::new(Y4mReader::new(
+            input,
+            cli.frame_rate(),
+            cli.yuv_matrix,
+            cli.decode_mode,
+        )?),
+        InputFormat::PngSequence => unreachable!("png sequences aren't read from a single input"),
+    })
+}
+fn open_input(path: &str) -> Result<Box<dyn Read>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(File::open(path)?))
+    }
+}
+fn create_output(path: &str) -> Result<BufWriter<Box<dyn Write>>> {
+    let output: Box<dyn Write> = if path == "-" {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    Ok(BufWriter::new(output))
+}
+
+/// for use with map_while, so that a lazy pipeline stops at the first error and reports it afterwards
+fn stop_on_error<T>(result: Result<T>, first_error: &mut Option<Error>) -> Option<T> {
+    match result {
+        Ok(x) => Some(x),
+        Err(e) => {
+            *first_error = Some(e);
+            None
+        }
+    }
 }
@@ -0,0 +1,166 @@
+use std::{cell::RefCell, io::Read, rc::Rc};
+
+use gif::{Extension, Repeat};
+
+/// container-level data that doesn't belong to any frame
+#[derive(Debug, Clone, Default)]
+pub struct GifMetadata {
+    pub repeat: Repeat,
+    /// comments and application extensions other than the loop count, in file order
+    pub extensions: Vec<RawExtension>,
+}
+impl GifMetadata {
+    /// for formats that count plays rather than repeats after the first, with 0 still meaning forever
+    pub fn play_count(&self) -> u32 {
+        match self.repeat {
+            Repeat::Infinite => 0,
+            Repeat::Finite(n) => n as u32 + 1,
+        }
+    }
+}
+#[derive(Debug, Clone)]
+pub struct RawExtension {
+    pub label: u8,
+    /// kept as the original sub-blocks because some extensions (e.g. XMP) depend on their layout
+    pub sub_blocks: Vec<Vec<u8>>,
+}
+impl RawExtension {
+    pub fn is_comment(&self) -> bool {
+        self.label == Extension::Comment as u8
+    }
+}
+
+const LOOP_EXTENSION_IDS: [&[u8]; 2] = [b"NETSCAPE2.0", b"ANIMEXTS1.0"];
+
+#[derive(Clone, Copy)]
+enum State {
+    Header,
+    Block,
+    ExtensionLabel,
+    SubBlocks,
+    ImageDescriptor,
+    Done,
+}
+
+/// passes bytes through to the gif decoder while picking out the extensions it doesn't expose
+pub(crate) struct MetadataReader<R: Read> {
+    inner: R,
+    metadata: Rc<RefCell<GifMetadata>>,
+    state: State,
+    pending: Vec<u8>,
+    /// the extension whose sub-blocks are currently being read, if it's one worth keeping
+    capturing: Option<RawExtension>,
+}
+impl<R: Read> MetadataReader<R> {
+    pub fn new(inner: R, metadata: Rc<RefCell<GifMetadata>>) -> Self {
+        Self {
+            inner,
+            metadata,
+            state: State::Header,
+            pending: Vec::new(),
+            capturing: None,
+        }
+    }
+    fn parse_pending(&mut self) {
+        let mut pending = std::mem::take(&mut self.pending);
+        let mut pos = 0;
+        while let Some(consumed) = self.parse_one(&pending[pos..]) {
+            pos += consumed;
+        }
+        pending.drain(..pos);
+        self.pending = pending;
+    }
+    /// returns how many bytes were consumed, or None if more are needed
+    fn parse_one(&mut self, bytes: &[u8]) -> Option<usize> {
+        match self.state {
+            State::Header => {
+                // signature, logical screen descriptor, then maybe the global colour table
+                let flags = *bytes.get(10)?;
+                let len = 13 + colour_table_len(flags);
+                (bytes.len() >= len).then(|| {
+                    self.state = State::Block;
+                    len
+                })
+            }
+            State::Block => {
+                self.state = match *bytes.first()? {
+                    0x21 => State::ExtensionLabel,
+                    0x2C => State::ImageDescriptor,
+                    _ => State::Done,
+                };
+                Some(1)
+            }
+            State::ExtensionLabel => {
+                let label = *bytes.first()?;
+                if label == Extension::Comment as u8 || label == Extension::Application as u8 {
+                    self.capturing = Some(RawExtension {
+                        label,
+                        sub_blocks: Vec::new(),
+                    });
+                }
+                self.state = State::SubBlocks;
+                Some(1)
+            }
+            State::SubBlocks => {
+                let len = *bytes.first()? as usize;
+                if len == 0 {
+                    if let Some(extension) = self.capturing.take() {
+                        self.finish_extension(extension);
+                    }
+                    self.state = State::Block;
+                    return Some(1);
+                }
+                let sub_block = bytes.get(1..1 + len)?;
+                if let Some(extension) = &mut self.capturing {
+                    extension.sub_blocks.push(sub_block.to_vec());
+                }
+                Some(1 + len)
+            }
+            State::ImageDescriptor => {
+                // descriptor, maybe the local colour table, then the lzw minimum code size
+                let flags = *bytes.get(8)?;
+                let len = 9 + colour_table_len(flags) + 1;
+                (bytes.len() >= len).then(|| {
+                    self.state = State::SubBlocks;
+                    len
+                })
+            }
+            State::Done => None,
+        }
+    }
+    fn finish_extension(&mut self, extension: RawExtension) {
+        let mut metadata = self.metadata.borrow_mut();
+        let is_loop_extension = !extension.is_comment()
+            && extension
+                .sub_blocks
+                .first()
+                .is_some_and(|id| LOOP_EXTENSION_IDS.contains(&id.as_slice()));
+        if !is_loop_extension {
+            metadata.extensions.push(extension);
+            return;
+        }
+        if let Some([1, lo, hi]) = extension.sub_blocks.get(1).map(Vec::as_slice) {
+            metadata.repeat = match u16::from_le_bytes([*lo, *hi]) {
+                0 => Repeat::Infinite,
+                n => Repeat::Finite(n),
+            };
+        }
+    }
+}
+impl<R: Read> Read for MetadataReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if !matches!(self.state, State::Done) {
+            self.pending.extend_from_slice(&buf[..n]);
+            self.parse_pending();
+        }
+        Ok(n)
+    }
+}
+fn colour_table_len(flags: u8) -> usize {
+    if flags & 0x80 == 0 {
+        0
+    } else {
+        3 * (2 << (flags & 0x07))
+    }
+}
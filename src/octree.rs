@@ -0,0 +1,142 @@
+use crate::image::Rgb;
+
+/// a node's depth is how many bits of each channel it has matched, so nodes at this depth are single
+/// colours
+const MAX_DEPTH: usize = 8;
+/// children use 0 for none, since the root is never anyone's child
+const NONE: u32 = 0;
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    children: [u32; 8],
+    /// pixels in this node's subtree, and the sum of their channels
+    count: u64,
+    sum: [u64; 3],
+    leaf: bool,
+}
+
+/// an octree colour quantizer that pixels are added to one at a time. whenever there are more than
+/// max_leaves leaves, the deepest node with the fewest pixels is merged into a single leaf, so memory
+/// stays bounded no matter how many unique colours there are
+pub struct Octree {
+    nodes: Vec<Node>,
+    /// indices of removed nodes, to reuse
+    free: Vec<u32>,
+    /// the nodes at each depth that have children
+    reducible: [Vec<u32>; MAX_DEPTH],
+    leaves: usize,
+    max_leaves: usize,
+}
+impl Octree {
+    pub fn new(max_leaves: usize) -> Self {
+        let mut reducible: [Vec<u32>; MAX_DEPTH] = Default::default();
+        reducible[0].push(0);
+        Self {
+            nodes: vec![Node::default()],
+            free: Vec::new(),
+            reducible,
+            leaves: 0,
+            max_leaves: max_leaves.max(1),
+        }
+    }
+    pub fn add(&mut self, rgb: Rgb) {
+        let mut index = 0;
+        for depth in 0.. {
+            let node = &mut self.nodes[index as usize];
+            node.count += 1;
+            node.sum[0] += rgb.r as u64;
+            node.sum[1] += rgb.g as u64;
+            node.sum[2] += rgb.b as u64;
+            if node.leaf {
+                break;
+            }
+            let shift = 7 - depth;
+            let branch = ((rgb.r >> shift & 1) << 2
+                | (rgb.g >> shift & 1) << 1
+                | rgb.b >> shift & 1) as usize;
+            let child = node.children[branch];
+            index = if child == NONE {
+                let child = self.new_node(depth + 1);
+                self.nodes[index as usize].children[branch] = child;
+                child
+            } else {
+                child
+            };
+        }
+        while self.leaves > self.max_leaves {
+            self.reduce();
+        }
+    }
+    /// each leaf's average colour and pixel count
+    pub fn leaves(&self) -> Vec<(Rgb, u64)> {
+        let mut ans = Vec::with_capacity(self.leaves);
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index as usize];
+            if node.leaf {
+                let [r, g, b] = node
+                    .sum
+                    .map(|sum| ((sum + node.count / 2) / node.count) as u8);
+                ans.push((Rgb::new(r, g, b), node.count));
+            } else {
+                stack.extend(node.children.iter().rev().filter(|&&child| child != NONE));
+            }
+        }
+        ans
+    }
+    /// merges leaves until there are at most max_n, and returns their average colours
+    pub fn palette(mut self, max_n: usize) -> Vec<Rgb> {
+        self.max_leaves = max_n.max(1);
+        while self.leaves > self.max_leaves {
+            self.reduce();
+        }
+        self.leaves().into_iter().map(|(rgb, _)| rgb).collect()
+    }
+    fn new_node(&mut self, depth: usize) -> u32 {
+        let node = Node {
+            leaf: depth == MAX_DEPTH,
+            ..Node::default()
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index as usize] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                (self.nodes.len() - 1) as u32
+            }
+        };
+        if depth == MAX_DEPTH {
+            self.leaves += 1;
+        } else {
+            self.reducible[depth].push(index);
+        }
+        index
+    }
+    /// the deepest reducible nodes only have leaves as children. the node already holds the sum of
+    /// its subtree, so it just forgets its children
+    fn reduce(&mut self) {
+        let Some(level) = self
+            .reducible
+            .iter_mut()
+            .rev()
+            .find(|level| !level.is_empty())
+        else {
+            return;
+        };
+        let (position, _) = level
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &index)| self.nodes[index as usize].count)
+            .unwrap();
+        let index = level.swap_remove(position) as usize;
+        let children = std::mem::take(&mut self.nodes[index].children);
+        for child in children.into_iter().filter(|&child| child != NONE) {
+            self.free.push(child);
+            self.leaves -= 1;
+        }
+        self.nodes[index].leaf = true;
+        self.leaves += 1;
+    }
+}
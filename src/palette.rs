@@ -1,55 +1,239 @@
 use std::collections::BinaryHeap;
 
-use indexmap::IndexSet;
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use log::{Level, info, log_enabled};
 
-use crate::image::{GifFrame, Rgb};
+use crate::{
+    colour_space::ColourSpace,
+    image::{GifFrame, Rgb},
+    octree::Octree,
+};
 
+/// how the palette is picked from the colours in every frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PaletteAlgorithm {
+    /// Median cut over the unique colours, ignoring how often each appears.
+    #[default]
+    MedianCut,
+    /// Median cut where each colour counts as many times as it appears, so large areas of similar
+    /// colour get more of the palette.
+    WeightedMedianCut,
+    /// Xiaolin Wu's quantizer, which splits boxes of colour to minimize their variance. Usually
+    /// faster than median cut, with similar or lower error.
+    Wu,
+    /// An octree that's fed pixels as they're undithered and merges similar colours as it goes, so
+    /// memory stays bounded no matter how many unique colours the input has.
+    Octree,
+}
+
+/// how far k-means refines the palette
+#[derive(Debug, Clone, Copy)]
+pub struct KmeansOptions {
+    /// 0 skips refinement
+    pub max_iterations: usize,
+    /// stops once no palette colour moves further than this in an iteration
+    pub convergence: f64,
+}
+
+/// leaves the octree is reduced to while pixels are being added, which bounds its memory
+const OCTREE_MAX_LEAVES: usize = 1 << 14;
+
+/// max_colours doesn't include the transparent index, which comes after the palette
 pub fn gen_palette(
     chunks: impl Iterator<Item = Vec<GifFrame>>,
     height: usize,
     width: usize,
+    max_colours: usize,
+    algorithm: PaletteAlgorithm,
+    kmeans_options: KmeansOptions,
+    mut colour_space: ColourSpace,
 ) -> Vec<Rgb> {
-    let mut colour_freq: IndexSet<Rgb> = IndexSet::default(); // for into_iter determinism
-    for chunk in chunks {
-        for frame in chunk {
-            for i in 0..height {
-                for j in 0..width {
-                    let cur = frame.image.get(i, j);
-                    colour_freq.insert(cur);
-                }
+    let opaque_pixels = chunks.flat_map(|chunk| {
+        chunk.into_iter().flat_map(move |frame| {
+            (0..height * width).filter_map(move |index| {
+                let cur = frame.image.get(index / width, index % width);
+                (!cur.is_transparent()).then(|| cur.rgb())
+            })
+        })
+    });
+    let mut colour_freq: IndexMap<Rgb, u64> = IndexMap::default(); // for into_iter determinism
+    let mut palette = if algorithm == PaletteAlgorithm::Octree {
+        let mut octree = Octree::new(OCTREE_MAX_LEAVES);
+        opaque_pixels.for_each(|rgb| octree.add(colour_space.encode(rgb)));
+        // the unique colours were never kept, so the error and k-means use the leaves instead
+        for (rgb, freq) in octree.leaves() {
+            *colour_freq.entry(rgb).or_default() += freq;
+        }
+        octree.palette(max_colours)
+    } else {
+        for rgb in opaque_pixels {
+            *colour_freq.entry(rgb).or_default() += 1;
+        }
+        // every colour fits in the palette, which converting would only make less exact
+        if colour_freq.len() <= max_colours {
+            colour_space = ColourSpace::Srgb;
+        }
+        if colour_space != ColourSpace::Srgb {
+            // several colours can share an encoding, so they're merged
+            let mut encoded: IndexMap<Rgb, u64> = IndexMap::default();
+            for (rgb, freq) in colour_freq {
+                *encoded.entry(colour_space.encode(rgb)).or_default() += freq;
+            }
+            colour_freq = encoded;
+        }
+        match algorithm {
+            PaletteAlgorithm::MedianCut => median_cut(
+                &mut colour_freq.keys().copied().collect::<Vec<Rgb>>(),
+                max_colours,
+            ),
+            PaletteAlgorithm::WeightedMedianCut => weighted_median_cut(
+                &mut colour_freq
+                    .iter()
+                    .map(|(&rgb, &freq)| (rgb, freq))
+                    .collect::<Vec<_>>(),
+                max_colours,
+            ),
+            PaletteAlgorithm::Wu => wu(&colour_freq, max_colours),
+            PaletteAlgorithm::Octree => unreachable!(),
+        }
+    };
+    if log_enabled!(Level::Info) {
+        info!(
+            "mean palette error: {:.3}",
+            mean_error(&colour_freq, &palette, colour_space)
+        );
+    }
+    if kmeans_options.max_iterations > 0 {
+        palette = kmeans(
+            &colour_freq,
+            &palette,
+            kmeans_options,
+            colour_space.weights(),
+        );
+        if log_enabled!(Level::Info) {
+            info!(
+                "mean palette error after k-means: {:.3}",
+                mean_error(&colour_freq, &palette, colour_space)
+            );
+        }
+    }
+    palette
+        .into_iter()
+        .map(|rgb| colour_space.decode(rgb))
+        .collect()
+}
+
+/// moves each palette colour to the weighted average of the colours nearest to it, until it
+/// converges or runs out of iterations. distances are weighted per channel, which in srgb matches
+/// the quantizer
+fn kmeans(
+    colour_freq: &IndexMap<Rgb, u64>,
+    palette: &[Rgb],
+    options: KmeansOptions,
+    weights: [f64; 3],
+) -> Vec<Rgb> {
+    let mut centroids: Vec<[f64; 3]> = palette
+        .iter()
+        .map(|x| [x.r as f64, x.g as f64, x.b as f64])
+        .collect();
+    for iteration in 1..=options.max_iterations {
+        let mut sums = vec![[0.0; 3]; centroids.len()];
+        let mut counts = vec![0_u64; centroids.len()];
+        for (&rgb, &freq) in colour_freq {
+            let colour = [rgb.r as f64, rgb.g as f64, rgb.b as f64];
+            let nearest = (0..centroids.len())
+                .min_by(|&a, &b| {
+                    distance_sq(colour, centroids[a], weights).total_cmp(&distance_sq(
+                        colour,
+                        centroids[b],
+                        weights,
+                    ))
+                })
+                .unwrap();
+            for (sum, channel) in sums[nearest].iter_mut().zip(colour) {
+                *sum += channel * freq as f64;
+            }
+            counts[nearest] += freq;
+        }
+        let mut max_movement: f64 = 0.0;
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            // a colour nothing is nearest to stays where it is
+            if count == 0 {
+                continue;
             }
+            let moved = sum.map(|channel| channel / count as f64);
+            max_movement = max_movement.max(distance_sq(*centroid, moved, weights).sqrt());
+            *centroid = moved;
+        }
+        if max_movement < options.convergence {
+            info!("k-means converged after {iteration} iterations");
+            break;
         }
     }
-    median_cut(&mut colour_freq.into_iter().collect::<Vec<Rgb>>(), 255)
+    centroids
+        .into_iter()
+        .map(|[r, g, b]| Rgb::new(r.round() as u8, g.round() as u8, b.round() as u8))
+        .collect()
+}
+fn distance_sq(a: [f64; 3], b: [f64; 3], weights: [f64; 3]) -> f64 {
+    (0..3)
+        .map(|i| weights[i] * (a[i] - b[i]) * (a[i] - b[i]))
+        .sum()
 }
+
+/// the average distance from each opaque pixel to its nearest palette colour
+fn mean_error(colour_freq: &IndexMap<Rgb, u64>, palette: &[Rgb], colour_space: ColourSpace) -> f64 {
+    // measured in srgb like the quantizer, so that it's comparable between colour spaces
+    let palette: Vec<Rgb> = palette.iter().map(|&x| colour_space.decode(x)).collect();
+    let mut total = 0.0;
+    let mut count = 0;
+    for (&rgb, &freq) in colour_freq {
+        let rgb = colour_space.decode(rgb);
+        let nearest = palette
+            .iter()
+            .map(|&x| rgb.distance_luma_sq(x))
+            .min()
+            .unwrap_or(0);
+        total += (nearest as f64).sqrt() * freq as f64;
+        count += freq;
+    }
+    if count == 0 {
+        0.0
+    } else {
+        total / count as f64
+    }
+}
+
+type MaxRangeAndDim = (usize, u8); //what was the max range, and which dim did it correspond to
+fn calc_max_range(lst: impl Iterator<Item = Rgb>) -> MaxRangeAndDim {
+    let (mut mn_r, mut mn_g, mut mn_b) = (255_usize, 255_usize, 255_usize);
+    let (mut mx_r, mut mx_g, mut mx_b) = (0_usize, 0_usize, 0_usize);
+    for x in lst {
+        mn_r = mn_r.min(x.r as usize);
+        mx_r = mx_r.max(x.r as usize);
+        mn_g = mn_g.min(x.g as usize);
+        mx_g = mx_g.max(x.g as usize);
+        mn_b = mn_b.min(x.b as usize);
+        mx_b = mx_b.max(x.b as usize);
+    }
+    *[
+        (mx_r - mn_r, 0_u8),
+        (mx_g - mn_g, 1_u8),
+        (mx_b - mn_b, 2_u8),
+    ]
+    .iter()
+    .max()
+    .unwrap()
+}
+
 /// frequency-blind
 fn median_cut(lst: &mut [Rgb], max_n: usize) -> Vec<Rgb> {
     if lst.len() <= max_n {
         return lst.to_vec();
     }
-    type MaxRangeAndDim = (usize, u8); //what was the max range, and which dim did it correspond to
     let mut pq: BinaryHeap<(MaxRangeAndDim, &mut [Rgb])> = BinaryHeap::new();
-    fn calc_max_range(lst: &[Rgb]) -> MaxRangeAndDim {
-        let (mut mn_r, mut mn_g, mut mn_b) = (255_usize, 255_usize, 255_usize);
-        let (mut mx_r, mut mx_g, mut mx_b) = (0_usize, 0_usize, 0_usize);
-        for x in lst {
-            mn_r = mn_r.min(x.r as usize);
-            mx_r = mx_r.max(x.r as usize);
-            mn_g = mn_g.min(x.g as usize);
-            mx_g = mx_g.max(x.g as usize);
-            mn_b = mn_b.min(x.b as usize);
-            mx_b = mx_b.max(x.b as usize);
-        }
-        *[
-            (mx_r - mn_r, 0_u8),
-            (mx_g - mn_g, 1_u8),
-            (mx_b - mn_b, 2_u8),
-        ]
-        .iter()
-        .max()
-        .unwrap()
-    }
-    pq.push((calc_max_range(lst), lst));
+    pq.push((calc_max_range(lst.iter().copied()), lst));
     let mut ans = Vec::with_capacity(max_n);
     while !pq.is_empty() && (ans.len() + pq.len()) < max_n {
         let ((_, split_dim), slice) = pq.pop().unwrap();
@@ -61,10 +245,10 @@ fn median_cut(lst: &mut [Rgb], max_n: usize) -> Vec<Rgb> {
         slice.select_nth_unstable_by_key(mid, |x| x.get(split_dim as usize));
         let (left, right) = slice.split_at_mut(mid);
         if !left.is_empty() {
-            pq.push((calc_max_range(left), left));
+            pq.push((calc_max_range(left.iter().copied()), left));
         }
         if !right.is_empty() {
-            pq.push((calc_max_range(right), right));
+            pq.push((calc_max_range(right.iter().copied()), right));
         }
     }
     pq.into_iter().for_each(|(_, slice)| {
@@ -83,3 +267,212 @@ fn median_cut(lst: &mut [Rgb], max_n: usize) -> Vec<Rgb> {
     });
     ans
 }
+
+/// splits at the weighted median instead, and averages each box weighted by frequency. the box with
+/// the largest range times pixel count is split first, so rare colours don't take palette entries
+/// from common ones
+fn weighted_median_cut(lst: &mut [(Rgb, u64)], max_n: usize) -> Vec<Rgb> {
+    if lst.len() <= max_n {
+        return lst.iter().map(|(rgb, _)| *rgb).collect();
+    }
+    type PriorityAndDim = (u64, u8);
+    fn calc_priority(lst: &[(Rgb, u64)]) -> PriorityAndDim {
+        let (range, dim) = calc_max_range(lst.iter().map(|(rgb, _)| *rgb));
+        let weight: u64 = lst.iter().map(|(_, freq)| freq).sum();
+        (range as u64 * weight, dim)
+    }
+    let mut pq: BinaryHeap<(PriorityAndDim, &mut [(Rgb, u64)])> = BinaryHeap::new();
+    pq.push((calc_priority(lst), lst));
+    let mut ans = Vec::with_capacity(max_n);
+    while !pq.is_empty() && (ans.len() + pq.len()) < max_n {
+        let ((_, split_dim), slice) = pq.pop().unwrap();
+        if slice.len() == 1 {
+            ans.push(slice[0].0);
+            continue;
+        }
+        slice.sort_unstable_by_key(|(rgb, _)| rgb.get(split_dim as usize));
+        let half: u64 = slice.iter().map(|(_, freq)| freq).sum::<u64>() / 2;
+        let mut cumulative = 0;
+        let mid = slice
+            .iter()
+            .position(|(_, freq)| {
+                cumulative += freq;
+                cumulative > half
+            })
+            .map_or(1, |i| i + 1)
+            .clamp(1, slice.len() - 1);
+        let (left, right) = slice.split_at_mut(mid);
+        pq.push((calc_priority(left), left));
+        pq.push((calc_priority(right), right));
+    }
+    pq.into_iter().for_each(|(_, slice)| {
+        let (mut r_sum, mut g_sum, mut b_sum, mut total) = (0, 0, 0, 0);
+        for (rgb, freq) in &*slice {
+            r_sum += rgb.r as u64 * freq;
+            g_sum += rgb.g as u64 * freq;
+            b_sum += rgb.b as u64 * freq;
+            total += freq;
+        }
+        ans.push(Rgb::new(
+            ((r_sum + total / 2) / total) as u8,
+            ((g_sum + total / 2) / total) as u8,
+            ((b_sum + total / 2) / total) as u8,
+        ));
+    });
+    ans
+}
+
+/// bits kept of each channel in wu's histogram
+const WU_BITS: usize = 6;
+/// one more than the number of bins, because the moment tables have a row of zeroes at index 0
+const WU_SIDE: usize = (1 << WU_BITS) + 1;
+/// weight, then the sums of r, g and b, then the sum of squared magnitudes
+type Moments = [f64; 5];
+
+/// a box of histogram bins, with the lower bounds exclusive
+#[derive(Debug, Clone, Copy)]
+struct WuBox {
+    lo: [usize; 3],
+    hi: [usize; 3],
+}
+
+/// variance-minimizing quantization, from Graphics Gems II. the histogram's cumulative moments let
+/// the weight, sum and variance of any box be found from its 8 corners, so every possible cut can be
+/// tried
+fn wu(colour_freq: &IndexMap<Rgb, u64>, max_n: usize) -> Vec<Rgb> {
+    if colour_freq.len() <= max_n {
+        return colour_freq.keys().copied().collect();
+    }
+    let mut moments: Vec<Moments> = vec![[0.0; 5]; WU_SIDE * WU_SIDE * WU_SIDE];
+    for (&rgb, &freq) in colour_freq {
+        let bin = [rgb.r, rgb.g, rgb.b].map(|x| ((x >> (8 - WU_BITS)) + 1) as usize);
+        let (r, g, b) = (rgb.r as f64, rgb.g as f64, rgb.b as f64);
+        let freq = freq as f64;
+        let m = &mut moments[wu_index(bin)];
+        m[0] += freq;
+        m[1] += r * freq;
+        m[2] += g * freq;
+        m[3] += b * freq;
+        m[4] += (r * r + g * g + b * b) * freq;
+    }
+    // prefix sums along each axis in turn make every entry the sum of the box from the origin to it
+    for dim in 0..3 {
+        let stride = WU_SIDE.pow(2 - dim as u32);
+        for index in 0..moments.len() {
+            if !(index / stride).is_multiple_of(WU_SIDE) {
+                let prev = moments[index - stride];
+                for (x, y) in moments[index].iter_mut().zip(prev) {
+                    *x += y;
+                }
+            }
+        }
+    }
+
+    let mut boxes = vec![WuBox {
+        lo: [0; 3],
+        hi: [WU_SIDE - 1; 3],
+    }];
+    let mut variances = vec![wu_variance(&moments, boxes[0])];
+    while boxes.len() < max_n {
+        let (next, &variance) = variances
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+        if variance <= 0.0 {
+            break;
+        }
+        match wu_cut(&moments, boxes[next]) {
+            Some((first, second)) => {
+                boxes[next] = first;
+                variances[next] = wu_variance(&moments, first);
+                boxes.push(second);
+                variances.push(wu_variance(&moments, second));
+            }
+            None => variances[next] = 0.0,
+        }
+    }
+    boxes
+        .into_iter()
+        .map(|cube| wu_volume(&moments, cube))
+        .filter(|m| m[0] > 0.0)
+        .map(|m| {
+            Rgb::new(
+                (m[1] / m[0]).round() as u8,
+                (m[2] / m[0]).round() as u8,
+                (m[3] / m[0]).round() as u8,
+            )
+        })
+        .collect()
+}
+fn wu_index([r, g, b]: [usize; 3]) -> usize {
+    (r * WU_SIDE + g) * WU_SIDE + b
+}
+/// the moments of the cube's cross-section at pos along dim, summed over the other two dims
+fn wu_face(moments: &[Moments], cube: WuBox, dim: usize, pos: usize) -> Moments {
+    let (d1, d2) = ((dim + 1) % 3, (dim + 2) % 3);
+    let mut ans = [0.0; 5];
+    for (c1, c2, sign) in [
+        (cube.hi[d1], cube.hi[d2], 1.0),
+        (cube.hi[d1], cube.lo[d2], -1.0),
+        (cube.lo[d1], cube.hi[d2], -1.0),
+        (cube.lo[d1], cube.lo[d2], 1.0),
+    ] {
+        let mut corner = [0; 3];
+        corner[dim] = pos;
+        corner[d1] = c1;
+        corner[d2] = c2;
+        for (x, y) in ans.iter_mut().zip(moments[wu_index(corner)]) {
+            *x += sign * y;
+        }
+    }
+    ans
+}
+fn wu_volume(moments: &[Moments], cube: WuBox) -> Moments {
+    sub_moments(
+        wu_face(moments, cube, 0, cube.hi[0]),
+        wu_face(moments, cube, 0, cube.lo[0]),
+    )
+}
+fn sub_moments(a: Moments, b: Moments) -> Moments {
+    std::array::from_fn(|i| a[i] - b[i])
+}
+/// the box's weighted variance, or 0 if it's a single bin and can't be cut
+fn wu_variance(moments: &[Moments], cube: WuBox) -> f64 {
+    if (0..3).all(|dim| cube.hi[dim] - cube.lo[dim] <= 1) {
+        return 0.0;
+    }
+    let m = wu_volume(moments, cube);
+    if m[0] == 0.0 {
+        return 0.0;
+    }
+    m[4] - (m[1] * m[1] + m[2] * m[2] + m[3] * m[3]) / m[0]
+}
+/// splits the box where it most reduces the total variance, or None if every cut leaves one side
+/// empty
+fn wu_cut(moments: &[Moments], cube: WuBox) -> Option<(WuBox, WuBox)> {
+    let whole = wu_volume(moments, cube);
+    // reducing variance is the same as maximizing sum^2/weight over the two halves
+    let score = |m: Moments| (m[1] * m[1] + m[2] * m[2] + m[3] * m[3]) / m[0];
+    let mut best: Option<(f64, usize, usize)> = None;
+    for dim in 0..3 {
+        let bottom = wu_face(moments, cube, dim, cube.lo[dim]);
+        for pos in cube.lo[dim] + 1..cube.hi[dim] {
+            let half = sub_moments(wu_face(moments, cube, dim, pos), bottom);
+            let other = sub_moments(whole, half);
+            if half[0] == 0.0 || other[0] == 0.0 {
+                continue;
+            }
+            let total = score(half) + score(other);
+            if best.is_none_or(|(max, _, _)| total > max) {
+                best = Some((total, dim, pos));
+            }
+        }
+    }
+    let (_, dim, pos) = best?;
+    let mut first = cube;
+    let mut second = cube;
+    first.hi[dim] = pos;
+    second.lo[dim] = pos;
+    Some((first, second))
+}
@@ -1,17 +1,20 @@
 use crate::{
+    error::Result,
     gpu,
     image::{GifFrame, Image, Rgb},
 };
-pub fn quantize_chunk(chunk: Vec<GifFrame>, palette: &Vec<Rgb>) -> Vec<GifFrame> {
+/// maps each frame to its own palette, which the frame keeps so the writer knows which one it used
+pub fn quantize_chunk(chunk: Vec<GifFrame>, palettes: Vec<&Vec<Rgb>>) -> Result<Vec<GifFrame>> {
     let images: Vec<&Image> = chunk.iter().map(|frame| &frame.image).collect();
-    let palettes = vec![palette; chunk.len()];
-    let output_images = gpu::run_shader_with_frames("nn_in_palette", images, palettes);
-    chunk
+    let output_images = gpu::run_shader_with_frames("nn_in_palette", images, palettes.clone())?;
+    Ok(chunk
         .into_iter()
         .zip(output_images)
-        .map(|(mut frame, output_image)| {
+        .zip(palettes)
+        .map(|((mut frame, output_image), palette)| {
             frame.image = output_image;
+            frame.palette = palette.clone();
             frame
         })
-        .collect()
+        .collect())
 }
@@ -0,0 +1,106 @@
+use std::io::{ErrorKind, Read};
+
+use gif::Repeat;
+
+use crate::{
+    error::{Error, Result},
+    frame_rate::FrameRate,
+    image::{GifFrame, Image, Rgba},
+    metadata::GifMetadata,
+    reader::DecodeMode,
+    source::{self, DecodeFrame, FrameCursor, FrameSource},
+};
+
+/// headerless rgb24 frames back to back, like ffmpeg's `-f rawvideo -pix_fmt rgb24`. each gifframe
+/// created will have the same height/width
+pub struct RawReader<R: Read> {
+    height: usize,
+    width: usize,
+    input: R,
+    buffer: Vec<u8>,
+    frame_rate: FrameRate,
+    cursor: FrameCursor,
+}
+impl<R: Read> RawReader<R> {
+    pub fn new(
+        input: R,
+        height: usize,
+        width: usize,
+        frame_rate: FrameRate,
+        decode_mode: DecodeMode,
+    ) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidInput("width or height is 0".to_string()));
+        }
+        Ok(Self {
+            height,
+            width,
+            input,
+            buffer: vec![0; 3 * height * width],
+            frame_rate,
+            cursor: FrameCursor::new(decode_mode),
+        })
+    }
+}
+impl<R: Read> DecodeFrame for RawReader<R> {
+    fn decode_frame(&mut self, index: usize) -> Result<Option<GifFrame>> {
+        let mut filled = 0;
+        while filled < self.buffer.len() {
+            match self.input.read(&mut self.buffer[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if filled == 0 {
+            return Ok(None);
+        }
+        if filled < self.buffer.len() {
+            return Err(Error::InvalidInput(format!(
+                "input ended {filled} bytes into a frame, but a {}x{} frame is {} bytes",
+                self.width,
+                self.height,
+                self.buffer.len()
+            )));
+        }
+        let image = Image {
+            buffer: self
+                .buffer
+                .chunks_exact(3)
+                .map(|c| Rgba::new(c[0], c[1], c[2], 255))
+                .collect(),
+            height: self.height,
+            width: self.width,
+        };
+        let delay = self.frame_rate.delay(index);
+        Ok(Some(GifFrame::new(image, Vec::new(), delay)))
+    }
+    fn cursor(&mut self) -> &mut FrameCursor {
+        &mut self.cursor
+    }
+}
+impl<R: Read> FrameSource for RawReader<R> {
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn metadata(&self) -> GifMetadata {
+        GifMetadata {
+            repeat: Repeat::Infinite,
+            ..GifMetadata::default()
+        }
+    }
+    fn is_paletted(&self) -> bool {
+        false
+    }
+}
+impl<R: Read> Iterator for RawReader<R> {
+    type Item = Result<GifFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        source::decode_next(self)
+    }
+}
@@ -1,103 +1,278 @@
-use std::fs::File;
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    io::{Cursor, Read},
+    rc::Rc,
+};
 
-use gif::{Decoder, DisposalMethod};
+use clap::ValueEnum;
+use gif::{Decoder, DisposalMethod, Frame};
+use log::warn;
 
-use crate::image::{GifFrame, Image, Rgb};
+use crate::{
+    error::{Error, Result},
+    image::{GifFrame, Image, Rgb, Rgba},
+    metadata::{GifMetadata, MetadataReader},
+    source::{self, DecodeFrame, FrameCursor, FrameSource},
+};
+
+/// what to do when a frame fails to decode partway through the gif
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DecodeMode {
+    /// Fail with the index of the frame that could not be decoded.
+    #[default]
+    Strict,
+    /// Keep the frames decoded so far and drop the rest.
+    Salvage,
+}
+
+impl DecodeMode {
+    /// what a reader should yield after the frame at this index fails to decode
+    pub(crate) fn on_frame_error(self, index: usize, e: Error) -> Option<Result<GifFrame>> {
+        if self == DecodeMode::Salvage && index > 0 {
+            warn!(
+                "frame {index} failed to decode ({e}), salvaging the {index} frames before it; frame {index} and all frames after it were lost"
+            );
+            return None;
+        }
+        Some(Err(Error::FrameDecode {
+            index,
+            cause: Box::new(e),
+        }))
+    }
+}
+
+/// what to do with frames that extend past the logical screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CanvasFit {
+    /// Cut off the parts of frames outside the logical screen.
+    #[default]
+    Clip,
+    /// Grow the canvas to fit every frame. This reads the whole input up front.
+    Expand,
+}
 
 /// each gifframe created will have the same height/width
-pub struct GifReader {
+pub struct GifReader<R: Read> {
     height: usize,
     width: usize,
     global_palette: Option<Vec<Rgb>>,
-    prev_frame: Option<Image>,
-    decoder_iter: <Decoder<File> as IntoIterator>::IntoIter,
+    compositor: Compositor,
+    decoder: Decoder<MetadataReader<Source<R>>>,
+    metadata: Rc<RefCell<GifMetadata>>,
+    cursor: FrameCursor,
+    warned_about_clipping: bool,
 }
-impl GifReader {
-    pub fn new(input: String) -> Self {
-        let decoder = make_decoder(input);
-        let height = decoder.height() as usize;
-        let width = decoder.width() as usize;
+impl<R: Read> GifReader<R> {
+    pub fn new(input: R, decode_mode: DecodeMode, canvas_fit: CanvasFit) -> Result<Self> {
+        let (source, bounds) = match canvas_fit {
+            CanvasFit::Clip => (Source::Direct(input), None),
+            CanvasFit::Expand => {
+                let mut bytes = Vec::new();
+                let mut input = input;
+                input.read_to_end(&mut bytes)?;
+                let bounds = frame_bounds(&bytes);
+                (Source::Buffered(Cursor::new(bytes)), bounds)
+            }
+        };
+        let metadata = Rc::default();
+        let decoder = make_decoder(source, Rc::clone(&metadata))?;
+        let mut height = decoder.height() as usize;
+        let mut width = decoder.width() as usize;
+        if let Some((bounds_height, bounds_width)) = bounds
+            && (bounds_height > height || bounds_width > width)
+        {
+            warn!(
+                "frames extend past the {width}x{height} logical screen, expanding the canvas to {}x{}",
+                bounds_width.max(width),
+                bounds_height.max(height)
+            );
+            height = height.max(bounds_height);
+            width = width.max(bounds_width);
+        }
         if width == 0 || height == 0 {
-            panic!("malformed gif: width or height is 0");
+            return Err(Error::MalformedGif("width or height is 0".to_string()));
         }
         let global_palette = decoder.global_palette().map(parse_palette);
-        let decoder_iter = decoder.into_iter();
-        Self {
+        Ok(Self {
             height,
             width,
-            prev_frame: None,
+            compositor: Compositor::new(height, width),
             global_palette,
-            decoder_iter,
+            decoder,
+            metadata,
+            cursor: FrameCursor::new(decode_mode),
+            warned_about_clipping: false,
+        })
+    }
+}
+impl<R: Read> DecodeFrame for GifReader<R> {
+    fn decode_frame(&mut self, index: usize) -> Result<Option<GifFrame>> {
+        // read before the pixels, since deinterlacing them clears the frame's interlace flag
+        let Some(info) = self.decoder.next_frame_info()? else {
+            return Ok(None);
+        };
+        let mut frame_raw = info.clone();
+        let mut buffer = vec![0; self.decoder.buffer_size()];
+        self.decoder.read_into_buffer(&mut buffer)?;
+        frame_raw.buffer = Cow::Owned(buffer);
+        let palette = if let Some(local) = &frame_raw.palette {
+            parse_palette(local.as_slice())
+        } else if let Some(global) = &self.global_palette {
+            global.clone()
+        } else {
+            return Err(Error::MissingPalette);
+        };
+        let (top, left) = (frame_raw.top as usize, frame_raw.left as usize);
+        let (height, width) = (frame_raw.height as usize, frame_raw.width as usize);
+        let visible_height = height.min(self.height.saturating_sub(top));
+        let visible_width = width.min(self.width.saturating_sub(left));
+        if (visible_height, visible_width) != (height, width) && !self.warned_about_clipping {
+            warn!(
+                "frame {} ({width}x{height} at {left},{top}) extends past the {}x{} logical screen, clipping it and any later frames that do the same",
+                index, self.width, self.height
+            );
+            self.warned_about_clipping = true;
         }
+        let image = self
+            .compositor
+            .draw(&frame_raw, visible_height, visible_width);
+
+        let frame = GifFrame {
+            dispose: frame_raw.dispose.into(),
+            interlaced: frame_raw.interlaced,
+            needs_user_input: frame_raw.needs_user_input,
+            transparent_index: frame_raw.transparent,
+            ..GifFrame::new(image, palette, frame_raw.delay)
+        };
+        Ok(Some(frame))
     }
-    pub fn width(&self) -> usize {
-        self.width
+    fn cursor(&mut self) -> &mut FrameCursor {
+        &mut self.cursor
     }
-    pub fn height(&self) -> usize {
+}
+impl<R: Read> FrameSource for GifReader<R> {
+    fn height(&self) -> usize {
         self.height
     }
+    fn width(&self) -> usize {
+        self.width
+    }
+    /// only complete once every frame has been read, since extensions can appear anywhere in the file
+    fn metadata(&self) -> GifMetadata {
+        self.metadata.borrow().clone()
+    }
+    fn is_paletted(&self) -> bool {
+        true
+    }
 }
-impl Iterator for GifReader {
-    type Item = GifFrame;
+impl<R: Read> Iterator for GifReader<R> {
+    type Item = Result<GifFrame>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let Some(Ok(frame_raw)) = self.decoder_iter.next() else {
-            return None;
-        };
-        let mut new_frame = self
-            .prev_frame
-            .clone()
-            .unwrap_or(Image::blank(self.height, self.width));
-        let pixels_raw: Vec<(u8, u8, u8, u8)> = frame_raw
-            .buffer
-            .chunks_exact(4)
-            .map(|c| (c[0], c[1], c[2], c[3]))
-            .collect();
+        source::decode_next(self)
+    }
+}
+/// the canvas that frames are drawn onto, which is disposed of in place after each frame
+struct Compositor {
+    canvas: Image,
+    /// what was under the last frame, if it's to be disposed of by restoring it
+    saved_region: Vec<Rgba>,
+}
+impl Compositor {
+    fn new(height: usize, width: usize) -> Self {
+        Self {
+            canvas: Image::blank(height, width),
+            saved_region: Vec::new(),
+        }
+    }
+    /// returns the canvas with the frame drawn over it. the frame must already be clipped to the
+    /// canvas, which is what the visible height and width are for
+    fn draw(&mut self, frame_raw: &Frame, visible_height: usize, visible_width: usize) -> Image {
         let (top, left) = (frame_raw.top as usize, frame_raw.left as usize);
-        let (height, width) = (frame_raw.height as usize, frame_raw.width as usize);
-        for i in 0..height {
-            for j in 0..width {
-                let (r, g, b, a) = pixels_raw[i * width + j];
-                if a == 0 {
-                    continue;
+        let canvas_width = self.canvas.width;
+        let region_rows = (top..top + visible_height)
+            .map(|i| i * canvas_width + left..i * canvas_width + left + visible_width);
+        if frame_raw.dispose == DisposalMethod::Previous {
+            self.saved_region.clear();
+            for row in region_rows.clone() {
+                self.saved_region
+                    .extend_from_slice(&self.canvas.buffer[row]);
+            }
+        }
+        let frame_rows = frame_raw
+            .buffer
+            .chunks_exact(4 * (frame_raw.width as usize).max(1));
+        for (row, frame_row) in region_rows.clone().zip(frame_rows) {
+            for (pixel, c) in self.canvas.buffer[row]
+                .iter_mut()
+                .zip(frame_row.chunks_exact(4))
+            {
+                // transparent pixels show what's beneath
+                if c[3] != 0 {
+                    *pixel = Rgba::new(c[0], c[1], c[2], c[3]);
                 }
-                *new_frame.get_mut(top + i, left + j) = Rgb::new(r, g, b);
             }
         }
-
-        let palette = if let Some(local) = frame_raw.palette {
-            parse_palette(local.as_slice())
-        } else if let Some(global) = &self.global_palette {
-            global.clone()
-        } else {
-            panic!("malformed gif: no global or local palette");
-        };
-        let frame = GifFrame::new(new_frame, palette, frame_raw.delay);
-        let mut new_prev = frame.clone();
-        for i in 0..self.height {
-            for j in 0..self.width {
-                *new_prev.image.get_mut(i, j) = match frame_raw.dispose {
-                    DisposalMethod::Any | DisposalMethod::Keep => new_prev.image.get(i, j),
-                    DisposalMethod::Background => Rgb::default(),
-                    DisposalMethod::Previous => self
-                        .prev_frame
-                        .as_ref()
-                        .unwrap_or(&Image::blank(self.height, self.width))
-                        .get(i, j),
+        let composited = self.canvas.clone();
+        // disposal only applies to the frame's own rectangle, the rest of the canvas is kept
+        match frame_raw.dispose {
+            DisposalMethod::Any | DisposalMethod::Keep => {}
+            // reveal the canvas' initial transparent state, like browsers do
+            DisposalMethod::Background => {
+                for row in region_rows {
+                    self.canvas.buffer[row].fill(Rgba::TRANSPARENT);
+                }
+            }
+            DisposalMethod::Previous => {
+                for (row, saved_row) in
+                    region_rows.zip(self.saved_region.chunks_exact(visible_width.max(1)))
+                {
+                    self.canvas.buffer[row].copy_from_slice(saved_row);
                 }
             }
         }
-        self.prev_frame = Some(new_prev.image);
-        Some(frame)
+        composited
+    }
+}
+
+/// the input, read all at once if the whole file is needed before decoding
+enum Source<R: Read> {
+    Direct(R),
+    Buffered(Cursor<Vec<u8>>),
+}
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Source::Direct(input) => input.read(buf),
+            Source::Buffered(input) => input.read(buf),
+        }
+    }
+}
+/// the smallest (height, width) that fits the logical screen and every frame, without decoding any
+/// pixels. a malformed file gives the bounds of the frames before the error, and the error itself is
+/// left for the real decode to report
+fn frame_bounds(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut options = gif::DecodeOptions::new();
+    options.skip_frame_decoding(true);
+    let mut decoder = options.read_info(bytes).ok()?;
+    let mut height = decoder.height() as usize;
+    let mut width = decoder.width() as usize;
+    while let Ok(Some(frame)) = decoder.read_next_frame() {
+        height = height.max(frame.top as usize + frame.height as usize);
+        width = width.max(frame.left as usize + frame.width as usize);
     }
+    Some((height, width))
 }
-fn make_decoder(file_name: String) -> Decoder<File> {
+fn make_decoder<R: Read>(
+    input: R,
+    metadata: Rc<RefCell<GifMetadata>>,
+) -> Result<Decoder<MetadataReader<R>>> {
     let mut decoder = gif::DecodeOptions::new();
     decoder.set_color_output(gif::ColorOutput::RGBA);
-    let file = File::open(file_name).unwrap();
-    decoder.read_info(file).unwrap()
+    Ok(decoder.read_info(MetadataReader::new(input, metadata))?)
 }
-fn parse_palette(palette_raw: &[u8]) -> Vec<Rgb> {
+pub(crate) fn parse_palette(palette_raw: &[u8]) -> Vec<Rgb> {
     let mut palette: Vec<Rgb> = palette_raw
         .chunks_exact(3)
         .map(|c| Rgb::new(c[0], c[1], c[2]))
@@ -0,0 +1,194 @@
+use std::{
+    cmp::Ordering,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    vec,
+};
+
+use gif::Repeat;
+
+use crate::{
+    apng::ApngReader,
+    error::{Error, Result},
+    frame_rate::FrameRate,
+    image::GifFrame,
+    metadata::GifMetadata,
+    reader::DecodeMode,
+    source::{self, DecodeFrame, FrameCursor, FrameSource},
+};
+
+/// one png per frame, from a directory or a printf-style pattern like frame_%04d.png. each gifframe
+/// created will have the same height/width
+pub struct PngSequence {
+    height: usize,
+    width: usize,
+    paths: vec::IntoIter<PathBuf>,
+    frame_rate: FrameRate,
+    cursor: FrameCursor,
+}
+impl PngSequence {
+    pub fn new(input: &str, frame_rate: FrameRate, decode_mode: DecodeMode) -> Result<Self> {
+        let paths = if Path::new(input).is_dir() {
+            list_dir(Path::new(input))?
+        } else {
+            expand_pattern(input)?
+        };
+        let first = paths
+            .first()
+            .ok_or_else(|| Error::InvalidInput(format!("no png frames found at {input}")))?;
+        // the canvas size comes from the first frame, without decoding its pixels yet
+        let decoder = png::Decoder::new(BufReader::new(File::open(first)?));
+        let reader = decoder.read_info()?;
+        let height = reader.info().height as usize;
+        let width = reader.info().width as usize;
+        Ok(Self {
+            height,
+            width,
+            paths: paths.into_iter(),
+            frame_rate,
+            cursor: FrameCursor::new(decode_mode),
+        })
+    }
+    fn read_frame(&self, path: &Path, index: usize) -> Result<GifFrame> {
+        let frame = ApngReader::new(File::open(path)?, DecodeMode::Strict)?
+            .decode_frame(0)?
+            .ok_or_else(|| Error::MalformedPng(format!("{} has no frames", path.display())))?;
+        let (height, width) = (frame.image.height, frame.image.width);
+        if (height, width) != (self.height, self.width) {
+            return Err(Error::InvalidInput(format!(
+                "{} is {width}x{height}, but the first frame is {}x{}",
+                path.display(),
+                self.width,
+                self.height
+            )));
+        }
+        // always treated as true-colour, so there's no palette to undither against
+        Ok(GifFrame::new(
+            frame.image,
+            Vec::new(),
+            self.frame_rate.delay(index),
+        ))
+    }
+}
+impl DecodeFrame for PngSequence {
+    fn decode_frame(&mut self, index: usize) -> Result<Option<GifFrame>> {
+        match self.paths.next() {
+            Some(path) => self.read_frame(&path, index).map(Some),
+            None => Ok(None),
+        }
+    }
+    fn cursor(&mut self) -> &mut FrameCursor {
+        &mut self.cursor
+    }
+}
+impl FrameSource for PngSequence {
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn metadata(&self) -> GifMetadata {
+        GifMetadata {
+            repeat: Repeat::Infinite,
+            ..GifMetadata::default()
+        }
+    }
+    fn is_paletted(&self) -> bool {
+        false
+    }
+}
+impl Iterator for PngSequence {
+    type Item = Result<GifFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        source::decode_next(self)
+    }
+}
+
+/// every png in the directory, in natural order so that frame_2 comes before frame_10
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_png = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+        if is_png && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    Ok(paths)
+}
+/// whether the input is a printf-style pattern rather than the name of a file that has a % in it
+pub fn is_pattern(input: &str) -> bool {
+    !Path::new(input).exists() && parse_pattern(input).is_some()
+}
+/// returns the text before and after the %d or %0Nd, the width, and whether it's zero padded
+fn parse_pattern(pattern: &str) -> Option<(&str, &str, usize, bool)> {
+    let (prefix, rest) = pattern.split_once('%')?;
+    let (spec, suffix) = rest.split_once('d')?;
+    if !spec.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let width = if spec.is_empty() {
+        0
+    } else {
+        spec.parse().ok()?
+    };
+    Some((prefix, suffix, width, spec.starts_with('0')))
+}
+/// expands a pattern with one %d or %0Nd in it, counting up until a frame is missing. like ffmpeg,
+/// the sequence may start at any index from 0 to 4
+fn expand_pattern(pattern: &str) -> Result<Vec<PathBuf>> {
+    let (prefix, suffix, width, zero_padded) = parse_pattern(pattern).ok_or_else(|| {
+        Error::InvalidInput(format!(
+            "{pattern} is neither a directory nor a pattern like frame_%04d.png"
+        ))
+    })?;
+    let path = |index: usize| {
+        PathBuf::from(if zero_padded {
+            format!("{prefix}{index:0width$}{suffix}")
+        } else {
+            format!("{prefix}{index:width$}{suffix}")
+        })
+    };
+    let Some(start) = (0..5).find(|&index| path(index).is_file()) else {
+        return Ok(Vec::new());
+    };
+    Ok((start..)
+        .map(path)
+        .take_while(|path| path.is_file())
+        .collect())
+}
+/// compares runs of digits by their value and everything else as text
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let a_digits = a[..a_end].trim_start_matches('0');
+            let b_digits = b[..b_end].trim_start_matches('0');
+            let order = a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits));
+            if order != Ordering::Equal {
+                return order;
+            }
+            a = &a[a_end..];
+            b = &b[b_end..];
+        } else if x != y {
+            return x.cmp(&y);
+        } else {
+            a = &a[x.len_utf8()..];
+            b = &b[y.len_utf8()..];
+        }
+    }
+}
@@ -1,4 +1,5 @@
 alias Rgb=vec3<u32>;
+alias Rgba=vec4<u32>; //a is either 0 (transparent) or 255
 
 struct GlobalInfo {
     num_frames:u32,
@@ -14,8 +15,8 @@ const WORKGROUP_SIZE_Z=1u;
 @group(0) @binding(0) var<uniform> global_info:GlobalInfo;
 @group(0) @binding(1) var<storage,read> palettes:array<Rgb>;
 @group(0) @binding(2) var<storage,read> palette_offsets:array<u32>;
-@group(0) @binding(3) var<storage,read> input_frames:array<Rgb>; //row major
-@group(0) @binding(4) var<storage,read_write> output_frames:array<Rgb>; //row major
+@group(0) @binding(3) var<storage,read> input_frames:array<Rgba>; //row major
+@group(0) @binding(4) var<storage,read_write> output_frames:array<Rgba>; //row major
 
 @compute
 @workgroup_size(WORKGROUP_SIZE_X,WORKGROUP_SIZE_Y,WORKGROUP_SIZE_Z)
@@ -26,18 +27,23 @@ fn nn_in_palette(@builtin(global_invocation_id) global_invocation_id: vec3<u32>)
     if frame_index>=global_info.num_frames || row_index>=global_info.height || col_index>=global_info.width {
         return;
     }
+    let index=index(frame_index,row_index,col_index);
+    let input=input_frames[index];
+    if input.a==0 {
+        output_frames[index]=input;
+        return;
+    }
     var best_dis=1000000u;
     var ans=Rgb(0,0,0);
-    let index=index(frame_index,row_index,col_index);
     for (var i=palette_offsets[frame_index]; i<palette_offsets[frame_index+1];i++) {
         let other=palettes[i];
-        let dis=distance_sq(input_frames[index],other);
+        let dis=distance_sq(input.rgb,other);
         if dis<best_dis {
             best_dis=dis;
             ans=other;
         }
     }
-    output_frames[index]=ans;
+    output_frames[index]=Rgba(ans,255);
 
 }
 
@@ -50,16 +56,26 @@ fn undither_frame(@builtin(global_invocation_id) global_invocation_id: vec3<u32>
     if frame_index>=global_info.num_frames || row_index>=global_info.height || col_index>=global_info.width {
         return;
     }
+    let centre_rgba=input_frames[index(frame_index,row_index,col_index)];
+    if centre_rgba.a==0 {
+        output_frames[index(frame_index,row_index,col_index)]=centre_rgba;
+        return;
+    }
+    let centre=centre_rgba.rgb;
     var local_input:array<array<Rgb,3>,3>;
+    var local_opaque:array<array<bool,3>,3>;
     for (var dr=-1;dr<=1;dr++) {
         for (var dc= -1; dc<=1;dc++) {
             let nr=u32(clamp(i32(row_index)+dr,0,i32(global_info.height-1)));
             let nc=u32(clamp(i32(col_index)+dc,0,i32(global_info.width-1)));
-            local_input[dr+1][dc+1]=input_frames[index(frame_index,nr,nc)];
+            let neighbour=input_frames[index(frame_index,nr,nc)];
+            let opaque=neighbour.a!=0;
+            local_opaque[dr+1][dc+1]=opaque;
+            //transparent neighbours stand in as the centre so they don't register as edges
+            local_input[dr+1][dc+1]=select(centre,neighbour.rgb,opaque);
         }
     }
-    
-    let centre=local_input[1][1];
+
     var luma:array<array<u32,3>,3>;
     for (var i=0;i<3;i++) {
         for (var j=0;j<3;j++) {
@@ -75,7 +91,7 @@ fn undither_frame(@builtin(global_invocation_id) global_invocation_id: vec3<u32>
     var sum_b=0u;
     var centre_weight:u32;
     if prewitt > prewitt_high_threshold {
-        output_frames[index(frame_index,row_index,col_index)]=centre;
+        output_frames[index(frame_index,row_index,col_index)]=centre_rgba;
         return;
     } else if prewitt > prewitt_low_threshold {
         centre_weight=24;
@@ -90,7 +106,7 @@ fn undither_frame(@builtin(global_invocation_id) global_invocation_id: vec3<u32>
 
     for (var i=0;i<3;i++) {
         for (var j=0;j<3;j++) {
-        if i==1 && j==1 {
+        if (i==1 && j==1) || !local_opaque[i][j] {
             continue;
         }
         let neighbour=local_input[i][j];
@@ -113,7 +129,7 @@ fn undither_frame(@builtin(global_invocation_id) global_invocation_id: vec3<u32>
         weight_len += weight;
     }
     }
-    output_frames[index(frame_index,row_index,col_index)]= Rgb(sum_r/weight_len,sum_g/weight_len,sum_b/weight_len);
+    output_frames[index(frame_index,row_index,col_index)]= Rgba(sum_r/weight_len,sum_g/weight_len,sum_b/weight_len,255);
 }
 
 fn index(frame:u32, pixel_i:u32, pixel_j:u32)->u32 {
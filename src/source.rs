@@ -0,0 +1,57 @@
+use crate::{error::Result, image::GifFrame, metadata::GifMetadata, reader::DecodeMode};
+
+/// a decoded input, where every frame yielded is already composited onto a canvas of the same size
+pub trait FrameSource: Iterator<Item = Result<GifFrame>> {
+    fn height(&self) -> usize;
+    fn width(&self) -> usize;
+    /// only guaranteed to be complete once every frame has been read
+    fn metadata(&self) -> GifMetadata {
+        GifMetadata::default()
+    }
+    /// whether the frames were limited to their palette, so dithering may need to be undone
+    fn is_paletted(&self) -> bool;
+}
+
+/// how far a reader that decodes one frame at a time has got
+#[derive(Debug)]
+pub(crate) struct FrameCursor {
+    decode_mode: DecodeMode,
+    index: usize,
+    failed: bool,
+}
+impl FrameCursor {
+    pub(crate) fn new(decode_mode: DecodeMode) -> Self {
+        Self {
+            decode_mode,
+            index: 0,
+            failed: false,
+        }
+    }
+}
+/// a reader whose Iterator::next is decode_next, so that they all count frames and handle errors
+/// the same way
+pub(crate) trait DecodeFrame {
+    /// returns None if the input ended cleanly between frames
+    fn decode_frame(&mut self, index: usize) -> Result<Option<GifFrame>>;
+    fn cursor(&mut self) -> &mut FrameCursor;
+}
+/// nothing more is read after a frame fails to decode, and what's yielded for it depends on the
+/// decode mode
+pub(crate) fn decode_next(reader: &mut impl DecodeFrame) -> Option<Result<GifFrame>> {
+    if reader.cursor().failed {
+        return None;
+    }
+    let index = reader.cursor().index;
+    let frame = reader.decode_frame(index);
+    let cursor = reader.cursor();
+    match frame {
+        Ok(frame) => {
+            cursor.index += 1;
+            frame.map(Ok)
+        }
+        Err(e) => {
+            cursor.failed = true;
+            cursor.decode_mode.on_frame_error(index, e)
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use gif::DisposalMethod;
+
 use crate::image::{GifFrame, Image};
 
+/// pixels are compared by colour rather than palette index, so this still works when frames have
+/// different palettes
 pub struct TransparencyOptimizer {
     prev_frame: Option<Image>,
     threshold: u32,
 }
-pub type TransparencyOutput = (GifFrame, Vec<bool>);
+/// the frame, which of its pixels are transparent, and how to dispose of it after it's shown
+pub type TransparencyOutput = (GifFrame, Vec<bool>, DisposalMethod);
 impl TransparencyOptimizer {
     pub fn new(threshold: u32) -> Self {
         Self {
@@ -16,20 +21,29 @@ impl TransparencyOptimizer {
         &mut self,
         frames: impl Iterator<Item = GifFrame>,
     ) -> impl Iterator<Item = TransparencyOutput> {
-        frames.map(|mut frame| {
-            let transparent_pixels = self.apply_transparency_once(&mut frame);
-            (frame, transparent_pixels)
-        })
+        TransparencyIter {
+            optimizer: self,
+            frames,
+            pending: None,
+        }
     }
-    fn apply_transparency_once(&mut self, frame: &mut GifFrame) -> Vec<bool> {
-        let mut transparent_pixels = vec![false; frame.image.width * frame.image.height];
-        let Some(prev_frame) = &self.prev_frame else {
-            self.prev_frame = Some(frame.clone().image);
-            return transparent_pixels;
-        };
+    /// returns the transparent pixels, and whether the canvas has to be cleared before this frame is
+    /// drawn, which is needed when an opaque pixel becomes transparent
+    fn apply_transparency_once(&mut self, frame: &mut GifFrame) -> (Vec<bool>, bool) {
         let image = &mut frame.image;
         let height = image.height;
         let width = image.width;
+        let mut transparent_pixels: Vec<bool> =
+            image.buffer.iter().map(|x| x.is_transparent()).collect();
+        let Some(prev_frame) = &self.prev_frame else {
+            self.prev_frame = Some(image.clone());
+            return (transparent_pixels, false);
+        };
+        let needs_clear = image
+            .buffer
+            .iter()
+            .zip(&prev_frame.buffer)
+            .any(|(cur, prev)| cur.is_transparent() && !prev.is_transparent());
         let mut max_i = 0;
         let mut min_i = height - 1;
         let mut max_j = 0;
@@ -38,10 +52,14 @@ impl TransparencyOptimizer {
             for j in 0..width {
                 let cur = image.get(i, j);
                 let prev = prev_frame.get(i, j);
-                if cur.distance_luma_sq(prev) < self.threshold * self.threshold {
-                    image.get_mut(i, j).r = prev.r;
-                    image.get_mut(i, j).g = prev.g;
-                    image.get_mut(i, j).b = prev.b;
+                let same = if needs_clear || cur.is_transparent() || prev.is_transparent() {
+                    // the canvas will be blank, so only transparent pixels can be skipped
+                    cur.is_transparent()
+                } else {
+                    cur.rgb().distance_luma_sq(prev.rgb()) < self.threshold * self.threshold
+                };
+                if same {
+                    *image.get_mut(i, j) = if needs_clear { cur } else { prev };
                     transparent_pixels[i * width + j] = true;
                 } else {
                     max_i = max_i.max(i);
@@ -58,6 +76,38 @@ impl TransparencyOptimizer {
         frame.local_height = max_i - min_i + 1;
         frame.local_width = max_j - min_j + 1;
         self.prev_frame = Some(frame.clone().image);
-        transparent_pixels
+        (transparent_pixels, needs_clear)
+    }
+}
+
+/// holds back one frame, so that its disposal can be changed if the next frame needs a clear canvas
+struct TransparencyIter<'a, I: Iterator<Item = GifFrame>> {
+    optimizer: &'a mut TransparencyOptimizer,
+    frames: I,
+    pending: Option<TransparencyOutput>,
+}
+impl<I: Iterator<Item = GifFrame>> Iterator for TransparencyIter<'_, I> {
+    type Item = TransparencyOutput;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(mut frame) = self.frames.next() else {
+                return self.pending.take();
+            };
+            let (transparent_pixels, needs_clear) =
+                self.optimizer.apply_transparency_once(&mut frame);
+            if needs_clear && let Some((prev, _, dispose)) = &mut self.pending {
+                // background disposal only clears the frame's own rectangle
+                prev.top = 0;
+                prev.left = 0;
+                prev.local_height = prev.image.height;
+                prev.local_width = prev.image.width;
+                *dispose = DisposalMethod::Background;
+            }
+            let output = (frame, transparent_pixels, DisposalMethod::Keep);
+            if let Some(prev) = self.pending.replace(output) {
+                return Some(prev);
+            }
+        }
     }
 }
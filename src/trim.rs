@@ -0,0 +1,84 @@
+use std::ops::Range;
+
+use crate::{error::Result, image::GifFrame};
+
+/// which frames to keep, both end-exclusive
+#[derive(Debug, Clone)]
+pub enum FrameRange {
+    Index(Range<usize>),
+    /// in centiseconds since the animation started, frames partially inside the range are kept with
+    /// their delay shortened to the part that's inside
+    Time(Range<u64>),
+}
+impl Default for FrameRange {
+    fn default() -> Self {
+        FrameRange::Index(0..usize::MAX)
+    }
+}
+
+/// drops frames outside of the range, and stops pulling frames once past it
+pub struct Trim<I: Iterator<Item = Result<GifFrame>>> {
+    frames: I,
+    range: FrameRange,
+    index: usize,
+    elapsed: u64,
+    finished: bool,
+}
+impl<I: Iterator<Item = Result<GifFrame>>> Trim<I> {
+    pub fn new(frames: I, range: FrameRange) -> Self {
+        Self {
+            frames,
+            range,
+            index: 0,
+            elapsed: 0,
+            finished: false,
+        }
+    }
+    /// returns whether to keep the frame, and updates its delay if it only partially overlaps
+    fn keep(&mut self, frame: &mut GifFrame) -> bool {
+        let index = self.index;
+        self.index += 1;
+        let shown_at = self.elapsed;
+        let hidden_at = shown_at + frame.delay as u64;
+        self.elapsed = hidden_at;
+        match &self.range {
+            FrameRange::Index(range) => {
+                self.finished = index + 1 >= range.end;
+                range.contains(&index)
+            }
+            FrameRange::Time(range) => {
+                self.finished = hidden_at >= range.end;
+                let visible_from = shown_at.max(range.start);
+                let visible_until = hidden_at.min(range.end);
+                if visible_from >= range.end || hidden_at < range.start {
+                    return false;
+                }
+                if frame.delay == 0 {
+                    // shown for an instant, so only keep it if that instant is in range
+                    return shown_at >= range.start;
+                }
+                if visible_until <= visible_from {
+                    return false;
+                }
+                frame.delay = (visible_until - visible_from) as u16;
+                true
+            }
+        }
+    }
+}
+impl<I: Iterator<Item = Result<GifFrame>>> Iterator for Trim<I> {
+    type Item = Result<GifFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let mut frame = match self.frames.next()? {
+                Ok(frame) => frame,
+                Err(e) => return Some(Err(e)),
+            };
+            if self.keep(&mut frame) {
+                return Some(Ok(frame));
+            }
+        }
+        None
+    }
+}
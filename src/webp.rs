@@ -0,0 +1,125 @@
+use std::io::Write;
+
+use gif::DisposalMethod;
+use image_webp::{ColorType, WebPEncoder};
+use log::warn;
+
+use crate::{error::Result, metadata::GifMetadata, transparency::TransparencyOutput};
+
+/// writes a lossless animated webp. the file starts with its total size, so frames are kept in memory
+/// until finish
+pub struct WebpWriter<W: Write, I: Iterator<Item = TransparencyOutput>> {
+    transparency_output: I,
+    height: usize,
+    width: usize,
+    loop_count: u16,
+    /// ANMF chunks
+    frames: Vec<u8>,
+    output: W,
+}
+impl<W: Write, I: Iterator<Item = TransparencyOutput>> WebpWriter<W, I> {
+    pub fn new(
+        transparency_output: I,
+        height: usize,
+        width: usize,
+        metadata: &GifMetadata,
+        output: W,
+    ) -> Result<Self> {
+        if !metadata.extensions.is_empty() {
+            warn!("webp output doesn't keep the input's comments and application extensions");
+        }
+        let loop_count = metadata.play_count().min(u16::MAX as u32) as u16;
+        Ok(Self {
+            transparency_output,
+            height,
+            width,
+            loop_count,
+            frames: Vec::new(),
+            output,
+        })
+    }
+    pub fn write_frame(&mut self) -> Result<bool> {
+        let Some((frame, transparent_pixels, dispose)) = self.transparency_output.next() else {
+            return Ok(false);
+        };
+        // offsets are stored halved, so an odd one is moved up or left with the extra pixels left
+        // transparent
+        let top = frame.top & !1;
+        let left = frame.left & !1;
+        let height = frame.top + frame.local_height - top;
+        let width = frame.left + frame.local_width - left;
+        let mut rgba = Vec::with_capacity(4 * height * width);
+        for i in top..top + height {
+            for j in left..left + width {
+                let index = i * frame.image.width + j;
+                if transparent_pixels[index] || i < frame.top || j < frame.left {
+                    rgba.extend_from_slice(&[0; 4]);
+                } else {
+                    let pixel = frame.image.buffer[index];
+                    rgba.extend_from_slice(&[pixel.r, pixel.g, pixel.b, 255]);
+                }
+            }
+        }
+        let mut image = Vec::new();
+        WebPEncoder::new(&mut image).encode(
+            &rgba,
+            width as u32,
+            height as u32,
+            ColorType::Rgba8,
+        )?;
+        let mut anmf = Vec::new();
+        anmf.extend_from_slice(&u24(left / 2));
+        anmf.extend_from_slice(&u24(top / 2));
+        anmf.extend_from_slice(&u24(width - 1));
+        anmf.extend_from_slice(&u24(height - 1));
+        anmf.extend_from_slice(&u24(frame.delay as usize * 10));
+        // alpha blending, so transparent pixels show the canvas beneath like in a gif
+        let flags = match dispose {
+            DisposalMethod::Background => 1,
+            _ => 0,
+        };
+        anmf.push(flags);
+        // a still webp is the RIFF header followed by its VP8L chunk, which is what a frame holds
+        anmf.extend_from_slice(&image[12..]);
+        write_chunk(&mut self.frames, b"ANMF", &anmf);
+        Ok(true)
+    }
+    pub fn finish(mut self) -> Result<()> {
+        let mut vp8x = Vec::new();
+        // animation and alpha
+        vp8x.push(1 << 1 | 1 << 4);
+        vp8x.extend_from_slice(&[0; 3]);
+        vp8x.extend_from_slice(&u24(self.width - 1));
+        vp8x.extend_from_slice(&u24(self.height - 1));
+        let mut anim = Vec::new();
+        // transparent background, as bgra
+        anim.extend_from_slice(&[0; 4]);
+        anim.extend_from_slice(&self.loop_count.to_le_bytes());
+
+        let mut chunks = Vec::new();
+        write_chunk(&mut chunks, b"VP8X", &vp8x);
+        write_chunk(&mut chunks, b"ANIM", &anim);
+        self.output.write_all(b"RIFF")?;
+        self.output
+            .write_all(&((4 + chunks.len() + self.frames.len()) as u32).to_le_bytes())?;
+        self.output.write_all(b"WEBP")?;
+        self.output.write_all(&chunks)?;
+        self.output.write_all(&self.frames)?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+fn u24(n: usize) -> [u8; 3] {
+    let [a, b, c, _] = (n as u32).to_le_bytes();
+    [a, b, c]
+}
+/// chunks are padded to an even length
+fn write_chunk(output: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(fourcc);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    output.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        output.push(0);
+    }
+}
@@ -1,63 +1,86 @@
-use std::{borrow::Cow, collections::HashMap, fs::File};
+use std::{borrow::Cow, collections::HashMap, io::Write};
 
-use gif::{DisposalMethod, Encoder, Frame};
+use gif::{AnyExtension, Encoder, Frame};
 
-use crate::{image::Rgb, transparency::TransparencyOutput};
+use crate::{
+    error::{Error, Result},
+    image::Rgb,
+    metadata::GifMetadata,
+    transparency::TransparencyOutput,
+};
 
-pub struct GifWriter<'a, I: Iterator<Item = TransparencyOutput>> {
-    encoder: Encoder<&'a mut File>,
+pub struct GifWriter<W: Write, I: Iterator<Item = TransparencyOutput>> {
+    encoder: Encoder<W>,
     transparency_output: I,
+    palette: Vec<Rgb>,
     transparent_index: u8,
     index_map: HashMap<Rgb, u8>,
+    /// whether frames quantized to a palette other than the global one get it as a local colour table
+    local_palettes: bool,
     width: usize,
     height: usize,
 }
-impl<'a, I: Iterator<Item = TransparencyOutput>> GifWriter<'a, I> {
+impl<W: Write, I: Iterator<Item = TransparencyOutput>> GifWriter<W, I> {
+    /// with local_palettes, palette is only the global one, and each frame is written with the palette
+    /// it was quantized to
     pub fn new(
         transparency_output: I,
         palette: Vec<Rgb>,
+        local_palettes: bool,
         height: usize,
         width: usize,
-        output_file: &'a mut File,
-    ) -> Self {
-        let palette_formatted: Vec<u8> = palette
-            .iter()
-            .flat_map(|x| [x.r, x.g, x.b])
-            .chain([0, 0, 0]) //pad for transparent index, don't put in kdtree
-            .collect();
-        let mut encoder =
-            Encoder::new(output_file, width as u16, height as u16, &palette_formatted).unwrap();
-        encoder.set_repeat(gif::Repeat::Infinite).unwrap();
-        assert!(palette.len() <= 255);
-        let transparent_index = palette.len() as u8;
-        let mut index_map = HashMap::default();
-        palette.iter().enumerate().for_each(|(i, x)| {
-            index_map.insert(*x, i as u8);
-        });
-        Self {
+        metadata: &GifMetadata,
+        output: W,
+    ) -> Result<Self> {
+        // frames are within the canvas, so once it fits they do too
+        let (Ok(gif_width), Ok(gif_height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(Error::InvalidInput(format!(
+                "{width}x{height} is larger than a gif can be, which is 65535x65535"
+            )));
+        };
+        let mut encoder = Encoder::new(output, gif_width, gif_height, &colour_table(&palette))?;
+        encoder.set_repeat(metadata.repeat)?;
+        for extension in &metadata.extensions {
+            let sub_blocks: Vec<&[u8]> = extension.sub_blocks.iter().map(Vec::as_slice).collect();
+            encoder.write_raw_extension(AnyExtension(extension.label), &sub_blocks)?;
+        }
+        let (index_map, transparent_index) = index_map(&palette);
+        Ok(Self {
+            palette,
             index_map,
             transparent_index,
+            local_palettes,
             encoder,
             transparency_output,
             width,
             height,
-        }
+        })
     }
-    pub fn write_frame(&mut self) -> bool {
-        let Some((frame, transparent_pixels)) = self.transparency_output.next() else {
-            return false;
+    pub fn write_frame(&mut self) -> Result<bool> {
+        let Some((frame, transparent_pixels, dispose)) = self.transparency_output.next() else {
+            return Ok(false);
+        };
+        let local_palette = self.local_palettes && frame.palette != self.palette;
+        let local_index_map = local_palette.then(|| index_map(&frame.palette));
+        let (index_map, transparent_index) = match &local_index_map {
+            Some((index_map, transparent_index)) => (index_map, *transparent_index),
+            None => (&self.index_map, self.transparent_index),
         };
         let mut indices: Vec<u8> = Vec::with_capacity(self.width * self.height);
-
-        for i in 0..frame.local_height {
+        let rows: Vec<usize> = if frame.interlaced {
+            interlaced_rows(frame.local_height).collect()
+        } else {
+            (0..frame.local_height).collect()
+        };
+        for i in rows {
             for j in 0..frame.local_width {
                 let global_i = frame.top + i;
                 let global_j = frame.left + j;
                 let cur = frame.image.get(global_i, global_j);
                 if transparent_pixels[global_i * self.width + global_j] {
-                    indices.push(self.transparent_index);
+                    indices.push(transparent_index);
                 } else {
-                    indices.push(self.index_map[&cur]);
+                    indices.push(index_map[&cur.rgb()]);
                 }
             }
         }
@@ -67,12 +90,43 @@ impl<'a, I: Iterator<Item = TransparencyOutput>> GifWriter<'a, I> {
             top: frame.top as u16,
             left: frame.left as u16,
             buffer: Cow::Borrowed(&indices),
-            dispose: DisposalMethod::Keep,
-            transparent: Some(self.transparent_index),
+            dispose,
+            transparent: Some(transparent_index),
+            palette: local_palette.then(|| colour_table(&frame.palette)),
             delay: frame.delay,
-            ..Default::default()
+            interlaced: frame.interlaced,
+            needs_user_input: frame.needs_user_input,
         };
-        self.encoder.write_frame(&frame_output).unwrap();
-        true
+        self.encoder.write_frame(&frame_output)?;
+        Ok(true)
+    }
+    /// writes the trailer and flushes, which would otherwise happen on drop with any error ignored
+    pub fn finish(self) -> Result<()> {
+        self.encoder.into_inner()?.flush()?;
+        Ok(())
     }
 }
+/// rgb triples, padded with one more entry for the transparent index
+fn colour_table(palette: &[Rgb]) -> Vec<u8> {
+    palette
+        .iter()
+        .flat_map(|x| [x.r, x.g, x.b])
+        .chain([0, 0, 0]) //pad for transparent index, don't put in kdtree
+        .collect()
+}
+/// the transparent index goes right after the palette, so that the colour table and the lzw code size
+/// are as small as the palette allows
+fn index_map(palette: &[Rgb]) -> (HashMap<Rgb, u8>, u8) {
+    assert!(palette.len() <= 255);
+    let mut index_map = HashMap::default();
+    palette.iter().enumerate().for_each(|(i, x)| {
+        index_map.insert(*x, i as u8);
+    });
+    (index_map, palette.len() as u8)
+}
+/// the order rows are stored in an interlaced gif frame
+fn interlaced_rows(height: usize) -> impl Iterator<Item = usize> {
+    [(0, 8), (4, 8), (2, 4), (1, 2)]
+        .into_iter()
+        .flat_map(move |(start, step)| (start..height).step_by(step))
+}
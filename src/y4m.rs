@@ -0,0 +1,246 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+
+use clap::ValueEnum;
+use gif::Repeat;
+use log::warn;
+
+use crate::{
+    error::{Error, Result},
+    frame_rate::FrameRate,
+    image::{GifFrame, Image, Rgba},
+    metadata::GifMetadata,
+    reader::DecodeMode,
+    source::{self, DecodeFrame, FrameCursor, FrameSource},
+};
+
+/// how the yuv was derived from rgb, which y4m doesn't record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum YuvMatrix {
+    /// Standard definition video.
+    Bt601,
+    /// HD video.
+    Bt709,
+}
+impl YuvMatrix {
+    /// the usual guess when the source doesn't say
+    pub fn for_height(height: usize) -> Self {
+        if height >= 720 {
+            YuvMatrix::Bt709
+        } else {
+            YuvMatrix::Bt601
+        }
+    }
+    /// returns (kr, kb)
+    fn coefficients(self) -> (f32, f32) {
+        match self {
+            YuvMatrix::Bt601 => (0.299, 0.114),
+            YuvMatrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+}
+
+/// yuv4mpeg2 with 8 bit samples. each gifframe created will have the same height/width
+pub struct Y4mReader<R: Read> {
+    height: usize,
+    width: usize,
+    input: BufReader<R>,
+    /// log2 of the (horizontal, vertical) chroma subsampling, or None for greyscale
+    chroma_shift: Option<(usize, usize)>,
+    matrix: YuvMatrix,
+    full_range: bool,
+    /// y, then u and v if there's chroma
+    planes: Vec<u8>,
+    frame_rate: FrameRate,
+    cursor: FrameCursor,
+}
+impl<R: Read> Y4mReader<R> {
+    /// the default frame rate is only used if the header doesn't have one, and the matrix is guessed
+    /// from the height if not given
+    pub fn new(
+        input: R,
+        default_frame_rate: FrameRate,
+        matrix: Option<YuvMatrix>,
+        decode_mode: DecodeMode,
+    ) -> Result<Self> {
+        let mut input = BufReader::new(input);
+        let header =
+            read_line(&mut input)?.ok_or_else(|| Error::MalformedY4m("empty input".to_string()))?;
+        let mut params = header.split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            return Err(Error::MalformedY4m(
+                "missing YUV4MPEG2 signature".to_string(),
+            ));
+        }
+        let mut width: usize = 0;
+        let mut height: usize = 0;
+        let mut frame_rate = default_frame_rate;
+        let mut colour_space = "420jpeg";
+        let mut full_range = false;
+        for param in params.filter(|param| !param.is_empty()) {
+            let Some((tag, value)) = param.split_at_checked(1) else {
+                continue;
+            };
+            match tag {
+                "W" => width = parse_number(param, value)?,
+                "H" => height = parse_number(param, value)?,
+                "F" => {
+                    let (num, den) = value
+                        .split_once(':')
+                        .ok_or_else(|| Error::MalformedY4m(format!("bad frame rate {param}")))?;
+                    let (num, den): (f64, f64) =
+                        (parse_number(param, num)?, parse_number(param, den)?);
+                    if num > 0.0 && den > 0.0 {
+                        frame_rate = FrameRate::from_fps(num / den);
+                    }
+                }
+                "I" if value != "p" && value != "?" => {
+                    warn!("y4m input is interlaced, treating its frames as progressive");
+                }
+                "C" => colour_space = value,
+                "X" if value == "COLORRANGE=FULL" => full_range = true,
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 {
+            return Err(Error::MalformedY4m("width or height is 0".to_string()));
+        }
+        let chroma_shift = match colour_space {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => Some((1, 1)),
+            "422" => Some((1, 0)),
+            "444" => Some((0, 0)),
+            "mono" => None,
+            _ => {
+                return Err(Error::InvalidInput(format!(
+                    "unsupported y4m colour space {colour_space}, only 8 bit 420, 422, 444 and mono are supported"
+                )));
+            }
+        };
+        let chroma_len = chroma_shift.map_or(0, |(x_shift, y_shift)| {
+            2 * width.div_ceil(1 << x_shift) * height.div_ceil(1 << y_shift)
+        });
+        Ok(Self {
+            height,
+            width,
+            input,
+            chroma_shift,
+            matrix: matrix.unwrap_or(YuvMatrix::for_height(height)),
+            full_range,
+            planes: vec![0; height * width + chroma_len],
+            frame_rate,
+            cursor: FrameCursor::new(decode_mode),
+        })
+    }
+    fn to_image(&self) -> Image {
+        let (kr, kb) = self.matrix.coefficients();
+        let kg = 1.0 - kr - kb;
+        let (luma_offset, luma_scale, chroma_scale) = if self.full_range {
+            (0.0, 1.0, 1.0)
+        } else {
+            (16.0, 255.0 / 219.0, 255.0 / 224.0)
+        };
+        let (luma, chroma) = self.planes.split_at(self.height * self.width);
+        let (u, v) = chroma.split_at(chroma.len() / 2);
+        let mut buffer = Vec::with_capacity(self.height * self.width);
+        for i in 0..self.height {
+            for j in 0..self.width {
+                let y = (luma[i * self.width + j] as f32 - luma_offset) * luma_scale;
+                let (cb, cr) = match self.chroma_shift {
+                    Some((x_shift, y_shift)) => {
+                        let chroma_width = self.width.div_ceil(1 << x_shift);
+                        let index = (i >> y_shift) * chroma_width + (j >> x_shift);
+                        (
+                            (u[index] as f32 - 128.0) * chroma_scale,
+                            (v[index] as f32 - 128.0) * chroma_scale,
+                        )
+                    }
+                    None => (0.0, 0.0),
+                };
+                let r = y + 2.0 * (1.0 - kr) * cr;
+                let g = y - 2.0 * kb * (1.0 - kb) / kg * cb - 2.0 * kr * (1.0 - kr) / kg * cr;
+                let b = y + 2.0 * (1.0 - kb) * cb;
+                // float to int casts saturate, so out of gamut values are clamped
+                buffer.push(Rgba::new(
+                    r.round() as u8,
+                    g.round() as u8,
+                    b.round() as u8,
+                    255,
+                ));
+            }
+        }
+        Image {
+            buffer,
+            height: self.height,
+            width: self.width,
+        }
+    }
+}
+impl<R: Read> DecodeFrame for Y4mReader<R> {
+    fn decode_frame(&mut self, index: usize) -> Result<Option<GifFrame>> {
+        let Some(frame_header) = read_line(&mut self.input)? else {
+            return Ok(None);
+        };
+        if !frame_header.starts_with("FRAME") {
+            return Err(Error::MalformedY4m(format!(
+                "expected a FRAME header, got {frame_header:?}"
+            )));
+        }
+        self.input
+            .read_exact(&mut self.planes)
+            .map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => {
+                    Error::MalformedY4m("input ended partway through a frame".to_string())
+                }
+                _ => e.into(),
+            })?;
+        let delay = self.frame_rate.delay(index);
+        Ok(Some(GifFrame::new(self.to_image(), Vec::new(), delay)))
+    }
+    fn cursor(&mut self) -> &mut FrameCursor {
+        &mut self.cursor
+    }
+}
+impl<R: Read> FrameSource for Y4mReader<R> {
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn metadata(&self) -> GifMetadata {
+        GifMetadata {
+            repeat: Repeat::Infinite,
+            ..GifMetadata::default()
+        }
+    }
+    fn is_paletted(&self) -> bool {
+        false
+    }
+}
+impl<R: Read> Iterator for Y4mReader<R> {
+    type Item = Result<GifFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        source::decode_next(self)
+    }
+}
+
+/// headers are ascii lines, returns None at the end of the input
+fn read_line(input: &mut impl BufRead) -> Result<Option<String>> {
+    let mut line = Vec::new();
+    if input.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(Error::MalformedY4m(
+            "input ended partway through a header".to_string(),
+        ));
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| Error::MalformedY4m("header isn't ascii".to_string()))
+}
+fn parse_number<T: std::str::FromStr>(param: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| Error::MalformedY4m(format!("bad header parameter {param}")))
+}
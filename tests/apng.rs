@@ -0,0 +1,168 @@
+use gif_compressor::{
+    apng::ApngReader,
+    image::{Disposal, GifFrame, Rgba},
+    reader::DecodeMode,
+};
+use png::{BitDepth, BlendOp, ColorType, DisposeOp, Encoder};
+
+const SIZE: u32 = 2;
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+
+/// a solid rgba frame
+struct SolidFrame {
+    top: u32,
+    left: u32,
+    size: u32,
+    colour: [u8; 4],
+    delay: (u16, u16),
+    blend: BlendOp,
+    dispose: DisposeOp,
+}
+impl SolidFrame {
+    fn new(colour: [u8; 4]) -> Self {
+        Self {
+            top: 0,
+            left: 0,
+            size: SIZE,
+            colour,
+            delay: (1, 100),
+            blend: BlendOp::Source,
+            dispose: DisposeOp::None,
+        }
+    }
+}
+
+fn write_apng(frames: &[SolidFrame]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut bytes, SIZE, SIZE);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, 0).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        for frame in frames {
+            // the position is checked against the old dimensions, and vice versa
+            writer.reset_frame_position().unwrap();
+            writer.set_frame_dimension(frame.size, frame.size).unwrap();
+            writer.set_frame_position(frame.left, frame.top).unwrap();
+            writer
+                .set_frame_delay(frame.delay.0, frame.delay.1)
+                .unwrap();
+            writer.set_blend_op(frame.blend).unwrap();
+            writer.set_dispose_op(frame.dispose).unwrap();
+            let data = frame.colour.repeat((frame.size * frame.size) as usize);
+            writer.write_image_data(&data).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+    bytes
+}
+
+fn read_apng(frames: &[SolidFrame]) -> Vec<GifFrame> {
+    let bytes = write_apng(frames);
+    ApngReader::new(bytes.as_slice(), DecodeMode::Strict)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+fn rgba([r, g, b, a]: [u8; 4]) -> Rgba {
+    Rgba::new(r, g, b, a)
+}
+
+#[test]
+fn blend_over_mixes_with_canvas() {
+    let frames = read_apng(&[
+        SolidFrame::new(RED),
+        SolidFrame {
+            blend: BlendOp::Over,
+            ..SolidFrame::new([0, 0, 255, 128])
+        },
+        SolidFrame {
+            blend: BlendOp::Over,
+            ..SolidFrame::new([0, 255, 0, 0])
+        },
+    ]);
+    let blended = rgba([127, 0, 128, 255]);
+    assert_eq!(frames[1].image.get(0, 0), blended);
+    // fully transparent pixels leave the canvas alone
+    assert_eq!(frames[2].image.get(1, 1), blended);
+}
+
+#[test]
+fn blend_source_replaces_canvas() {
+    let frames = read_apng(&[
+        SolidFrame::new(RED),
+        SolidFrame::new([0, 0, 255, 128]),
+        SolidFrame::new([0, 255, 0, 0]),
+    ]);
+    assert_eq!(frames[1].image.get(0, 0), rgba([0, 0, 255, 255]));
+    assert!(frames[2].image.get(0, 0).is_transparent());
+}
+
+#[test]
+fn previous_on_first_frame_acts_like_background() {
+    let frames = read_apng(&[
+        SolidFrame {
+            dispose: DisposeOp::Previous,
+            ..SolidFrame::new(RED)
+        },
+        SolidFrame {
+            top: 1,
+            left: 1,
+            size: 1,
+            ..SolidFrame::new(GREEN)
+        },
+    ]);
+    assert_eq!(frames[0].dispose, Disposal::Background);
+    assert_eq!(frames[0].image.get(0, 0), rgba(RED));
+    assert!(frames[1].image.get(0, 0).is_transparent());
+    assert_eq!(frames[1].image.get(1, 1), rgba(GREEN));
+}
+
+#[test]
+fn previous_restores_frame_rectangle() {
+    let frames = read_apng(&[
+        SolidFrame::new(RED),
+        SolidFrame {
+            size: 1,
+            dispose: DisposeOp::Previous,
+            ..SolidFrame::new(GREEN)
+        },
+        SolidFrame {
+            top: 1,
+            left: 1,
+            size: 1,
+            ..SolidFrame::new([0, 0, 255, 255])
+        },
+    ]);
+    assert_eq!(frames[1].dispose, Disposal::Previous);
+    assert_eq!(frames[1].image.get(0, 0), rgba(GREEN));
+    assert_eq!(frames[2].image.get(0, 0), rgba(RED));
+}
+
+#[test]
+fn delays_are_rounded_without_drifting() {
+    let frames = read_apng(
+        &(0..30)
+            .map(|_| SolidFrame {
+                delay: (1, 30),
+                ..SolidFrame::new(RED)
+            })
+            .collect::<Vec<_>>(),
+    );
+    let delays: Vec<_> = frames.iter().map(|frame| frame.delay).collect();
+    // 3.33 centiseconds each, so rounding each frame on its own would add up to 90
+    assert_eq!(delays[..3], [3, 4, 3]);
+    assert_eq!(delays.iter().map(|&delay| delay as u32).sum::<u32>(), 100);
+}
+
+#[test]
+fn zero_denominator_means_centiseconds() {
+    let frames = read_apng(&[SolidFrame {
+        delay: (7, 0),
+        ..SolidFrame::new(RED)
+    }]);
+    assert_eq!(frames[0].delay, 7);
+}
@@ -0,0 +1,135 @@
+use gif_compressor::{
+    decimate::{Decimate, DecimateRate},
+    image::{GifFrame, Image, Rgba},
+};
+
+/// a frame with one pixel per given red value, where None is transparent
+fn frame(reds: &[Option<u8>], delay: u16) -> GifFrame {
+    let mut image = Image::blank(1, reds.len());
+    for (j, red) in reds.iter().enumerate() {
+        if let Some(red) = *red {
+            *image.get_mut(0, j) = Rgba::new(red, 0, 0, 255);
+        }
+    }
+    GifFrame::new(image, Vec::new(), delay)
+}
+
+/// 1x1 frames whose red channel is their index
+fn tagged(delays: &[u16]) -> Vec<GifFrame> {
+    delays
+        .iter()
+        .enumerate()
+        .map(|(i, &delay)| frame(&[Some(i as u8)], delay))
+        .collect()
+}
+
+fn decimate(frames: Vec<GifFrame>, rate: DecimateRate, blend: bool) -> Vec<GifFrame> {
+    Decimate::new(frames.into_iter().map(Ok), rate, blend)
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// (tag, delay) of each kept frame
+fn summarize(frames: &[GifFrame]) -> Vec<(u8, u16)> {
+    frames
+        .iter()
+        .map(|frame| (frame.image.get(0, 0).r, frame.delay))
+        .collect()
+}
+
+#[test]
+fn keep_every_adds_dropped_delays_to_kept_frame() {
+    let frames = decimate(
+        tagged(&[10, 20, 30, 40, 50]),
+        DecimateRate::KeepEvery(2),
+        false,
+    );
+    assert_eq!(summarize(&frames), [(0, 30), (2, 70), (4, 50)]);
+}
+
+#[test]
+fn max_fps_merges_frames_into_one_slot() {
+    // shown at 0, 4, 8, 12, 16 and 20, with slots starting every 10
+    let frames = decimate(tagged(&[4; 6]), DecimateRate::MaxFps(10.0), false);
+    assert_eq!(summarize(&frames), [(0, 12), (3, 8), (5, 4)]);
+}
+
+#[test]
+fn max_fps_keeps_frames_slower_than_the_limit() {
+    let frames = decimate(tagged(&[10, 25, 10]), DecimateRate::MaxFps(10.0), false);
+    assert_eq!(summarize(&frames), [(0, 10), (1, 25), (2, 10)]);
+}
+
+#[test]
+fn max_fps_folds_zero_delay_frames() {
+    let frames = decimate(tagged(&[0, 0, 10, 0]), DecimateRate::MaxFps(10.0), false);
+    assert_eq!(summarize(&frames), [(0, 10), (3, 0)]);
+}
+
+#[test]
+fn blend_dropped_averages_by_delay() {
+    let frames = decimate(
+        vec![frame(&[Some(200)], 10), frame(&[Some(0)], 30)],
+        DecimateRate::KeepEvery(2),
+        true,
+    );
+    assert_eq!(summarize(&frames), [(50, 40)]);
+}
+
+#[test]
+fn blend_dropped_gives_zero_delay_frames_some_weight() {
+    let frames = decimate(
+        vec![frame(&[Some(200)], 0), frame(&[Some(0)], 3)],
+        DecimateRate::KeepEvery(2),
+        true,
+    );
+    assert_eq!(summarize(&frames), [(50, 3)]);
+}
+
+#[test]
+fn blend_dropped_only_averages_opaque_pixels() {
+    let frames = decimate(
+        vec![
+            frame(&[None, Some(40), None], 10),
+            frame(&[Some(100), Some(80), None], 10),
+        ],
+        DecimateRate::KeepEvery(2),
+        true,
+    );
+    let image = &frames[0].image;
+    assert_eq!(image.get(0, 0), Rgba::new(100, 0, 0, 255));
+    assert_eq!(image.get(0, 1), Rgba::new(60, 0, 0, 255));
+    assert!(image.get(0, 2).is_transparent());
+}
+
+#[test]
+fn blend_dropped_leaves_lone_frames_alone() {
+    let frames = decimate(
+        vec![
+            frame(&[Some(10)], 10),
+            frame(&[Some(20)], 10),
+            frame(&[None], 10),
+        ],
+        DecimateRate::KeepEvery(2),
+        true,
+    );
+    assert_eq!(frames.len(), 2);
+    assert!(frames[1].image.get(0, 0).is_transparent());
+    assert_eq!(frames[1].delay, 10);
+}
+
+#[test]
+fn max_fps_handles_tiny_intervals() {
+    let frames = decimate(tagged(&[10, 10]), DecimateRate::MaxFps(1e20), false);
+    assert_eq!(summarize(&frames), [(0, 10), (1, 10)]);
+}
+
+#[test]
+fn blend_dropped_handles_long_groups_of_long_frames() {
+    let frames = decimate(
+        (0..300).map(|_| frame(&[Some(255)], 60000)).collect(),
+        DecimateRate::KeepEvery(300),
+        true,
+    );
+    assert_eq!(frames[0].image.get(0, 0), Rgba::new(255, 0, 0, 255));
+}
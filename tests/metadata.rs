@@ -0,0 +1,209 @@
+use std::{borrow::Cow, io::Read};
+
+use gif::{AnyExtension, Encoder, Extension, Frame, Repeat};
+use gif_compressor::{
+    image::Rgba,
+    metadata::GifMetadata,
+    reader::{CanvasFit, DecodeMode, GifReader},
+    source::FrameSource,
+};
+
+const SIZE: u16 = 2;
+const PALETTE: [u8; 6] = [0, 0, 0, 255, 255, 255];
+const XMP_ID: &[u8] = b"XMP DataXMP";
+const ICC_ID: &[u8] = b"ICCRGBG1012";
+
+/// a block of the gif to write, in file order
+enum Block<'a> {
+    Repeat(Repeat),
+    Raw(u8, &'a [&'a [u8]]),
+    Frame(Option<&'a [u8]>, u8),
+}
+
+fn write_gif(blocks: &[Block]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut bytes, SIZE, SIZE, &PALETTE).unwrap();
+        for block in blocks {
+            match *block {
+                Block::Repeat(repeat) => encoder.set_repeat(repeat).unwrap(),
+                Block::Raw(label, data) => encoder
+                    .write_raw_extension(AnyExtension(label), data)
+                    .unwrap(),
+                Block::Frame(palette, index) => encoder
+                    .write_frame(&Frame {
+                        width: SIZE,
+                        height: SIZE,
+                        palette: palette.map(<[u8]>::to_vec),
+                        buffer: Cow::Owned(vec![index; SIZE as usize * SIZE as usize]),
+                        ..Default::default()
+                    })
+                    .unwrap(),
+            }
+        }
+    }
+    bytes
+}
+
+/// hands out at most one byte per read, so every block is split across reads
+struct OneByteReader<'a>(&'a [u8]);
+impl Read for OneByteReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(1);
+        self.0.read(&mut buf[..n])
+    }
+}
+
+fn read_metadata(input: impl Read) -> GifMetadata {
+    let mut reader = GifReader::new(input, DecodeMode::Strict, CanvasFit::Clip).unwrap();
+    for frame in reader.by_ref() {
+        frame.unwrap();
+    }
+    reader.metadata()
+}
+
+/// (label, sub-blocks)
+type Summary = (u8, Vec<Vec<u8>>);
+
+/// the repeat and each extension's label and sub-blocks
+fn summarize(metadata: &GifMetadata) -> (Repeat, Vec<Summary>) {
+    let extensions = metadata
+        .extensions
+        .iter()
+        .map(|extension| (extension.label, extension.sub_blocks.clone()))
+        .collect();
+    (metadata.repeat, extensions)
+}
+
+fn everything() -> Vec<u8> {
+    let icc_profile = vec![7; 300];
+    write_gif(&[
+        Block::Repeat(Repeat::Finite(3)),
+        Block::Raw(Extension::Comment as u8, &[b"first"]),
+        Block::Raw(Extension::Application as u8, &[XMP_ID, b"<x:xmpmeta/>"]),
+        Block::Frame(None, 1),
+        Block::Raw(Extension::Application as u8, &[ICC_ID, &icc_profile]),
+        Block::Frame(Some(&[0; 24]), 7),
+        Block::Raw(Extension::Comment as u8, &[b"second"]),
+    ])
+}
+
+#[test]
+fn finite_loop_count() {
+    let bytes = write_gif(&[Block::Repeat(Repeat::Finite(5)), Block::Frame(None, 0)]);
+    let metadata = read_metadata(bytes.as_slice());
+    assert_eq!(metadata.repeat, Repeat::Finite(5));
+    assert_eq!(metadata.play_count(), 6);
+    assert!(metadata.extensions.is_empty());
+}
+
+#[test]
+fn infinite_loop_count() {
+    let bytes = write_gif(&[Block::Repeat(Repeat::Infinite), Block::Frame(None, 0)]);
+    let metadata = read_metadata(bytes.as_slice());
+    assert_eq!(metadata.repeat, Repeat::Infinite);
+    assert_eq!(metadata.play_count(), 0);
+    assert!(metadata.extensions.is_empty());
+}
+
+#[test]
+fn animexts_loop_count() {
+    let bytes = write_gif(&[
+        Block::Raw(Extension::Application as u8, &[b"ANIMEXTS1.0", &[1, 2, 1]]),
+        Block::Frame(None, 0),
+    ]);
+    let metadata = read_metadata(bytes.as_slice());
+    assert_eq!(metadata.repeat, Repeat::Finite(258));
+    assert!(metadata.extensions.is_empty());
+}
+
+#[test]
+fn missing_loop_extension_plays_once() {
+    let bytes = write_gif(&[Block::Frame(None, 0)]);
+    let metadata = read_metadata(bytes.as_slice());
+    assert_eq!(metadata.repeat, Repeat::Finite(0));
+    assert_eq!(metadata.play_count(), 1);
+}
+
+#[test]
+fn comments_are_kept_in_order() {
+    let bytes = write_gif(&[
+        Block::Raw(Extension::Comment as u8, &[b"one"]),
+        Block::Frame(None, 0),
+        Block::Raw(Extension::Comment as u8, &[b"two", b"three"]),
+        Block::Frame(None, 1),
+        Block::Raw(Extension::Comment as u8, &[b"four"]),
+    ]);
+    let metadata = read_metadata(bytes.as_slice());
+    let comments: Vec<_> = metadata
+        .extensions
+        .iter()
+        .inspect(|extension| assert!(extension.is_comment()))
+        .map(|extension| extension.sub_blocks.clone())
+        .collect();
+    assert_eq!(
+        comments,
+        [
+            vec![b"one".to_vec()],
+            vec![b"two".to_vec(), b"three".to_vec()],
+            vec![b"four".to_vec()],
+        ]
+    );
+}
+
+#[test]
+fn application_extensions_keep_their_sub_blocks() {
+    let metadata = read_metadata(everything().as_slice());
+    let (repeat, extensions) = summarize(&metadata);
+    assert_eq!(repeat, Repeat::Finite(3));
+    let application = Extension::Application as u8;
+    let applications: Vec<_> = extensions
+        .iter()
+        .filter(|(label, _)| *label == application)
+        .collect();
+    assert_eq!(applications.len(), 2);
+    assert_eq!(
+        applications[0].1,
+        [XMP_ID.to_vec(), b"<x:xmpmeta/>".to_vec()]
+    );
+    // the profile is longer than a sub-block, so it was split
+    assert_eq!(
+        applications[1].1,
+        [ICC_ID.to_vec(), vec![7; 255], vec![7; 45]]
+    );
+}
+
+#[test]
+fn one_byte_reads_match_whole_reads() {
+    let bytes = everything();
+    let whole = read_metadata(bytes.as_slice());
+    let split = read_metadata(OneByteReader(&bytes));
+    assert_eq!(summarize(&split), summarize(&whole));
+    assert_eq!(split.extensions.len(), 4);
+}
+
+#[test]
+fn local_colour_tables_are_skipped() {
+    // 8 entries, bigger than the global table, with a comment right after the frame using it
+    let mut local_palette = [0; 24];
+    local_palette[21..].copy_from_slice(&[10, 20, 30]);
+    let bytes = write_gif(&[
+        Block::Frame(Some(&local_palette), 7),
+        Block::Raw(Extension::Comment as u8, &[b"after"]),
+        Block::Frame(None, 1),
+    ]);
+    let mut reader = GifReader::new(bytes.as_slice(), DecodeMode::Strict, CanvasFit::Clip).unwrap();
+    let frames: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+    assert_eq!(
+        frames[0].image.get(0, 0),
+        Rgba {
+            r: 10,
+            g: 20,
+            b: 30,
+            a: 255
+        }
+    );
+    let metadata = reader.metadata();
+    assert_eq!(metadata.extensions.len(), 1);
+    assert_eq!(metadata.extensions[0].sub_blocks, [b"after".to_vec()]);
+}
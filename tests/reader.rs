@@ -0,0 +1,316 @@
+use std::borrow::Cow;
+
+use gif::{DecodeOptions, DisposalMethod, Encoder, Frame};
+use gif_compressor::{
+    image::{GifFrame, Image, Rgba},
+    metadata::GifMetadata,
+    reader::{CanvasFit, DecodeMode, GifReader},
+    writer::GifWriter,
+};
+
+const SIZE: u16 = 4;
+const PALETTE: [u8; 12] = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+const RED: Rgba = Rgba {
+    r: 255,
+    g: 0,
+    b: 0,
+    a: 255,
+};
+const GREEN: Rgba = Rgba {
+    r: 0,
+    g: 255,
+    b: 0,
+    a: 255,
+};
+
+/// (top, left, size, palette index, disposal)
+type SolidFrame = (u16, u16, u16, u8, DisposalMethod);
+
+fn write_gif(frames: &[SolidFrame], transparent: Option<u8>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut bytes, SIZE, SIZE, &PALETTE).unwrap();
+        for &(top, left, size, index, dispose) in frames {
+            let buffer = vec![index; size as usize * size as usize];
+            encoder
+                .write_frame(&Frame {
+                    top,
+                    left,
+                    width: size,
+                    height: size,
+                    dispose,
+                    transparent,
+                    buffer: Cow::Owned(buffer),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+    }
+    bytes
+}
+
+fn read_gif(frames: &[SolidFrame]) -> Vec<GifFrame> {
+    read_gif_with_transparency(frames, None)
+}
+
+fn read_gif_with_transparency(frames: &[SolidFrame], transparent: Option<u8>) -> Vec<GifFrame> {
+    read_gif_with_options(frames, transparent, CanvasFit::Clip)
+}
+
+fn read_gif_with_options(
+    frames: &[SolidFrame],
+    transparent: Option<u8>,
+    canvas_fit: CanvasFit,
+) -> Vec<GifFrame> {
+    let bytes = write_gif(frames, transparent);
+    GifReader::new(bytes.as_slice(), DecodeMode::Strict, canvas_fit)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// full red canvas, then a green 2x2 square at (1, 1) with the given disposal, then a tiny frame in the corner
+fn read_with_square_disposal(dispose: DisposalMethod) -> Vec<GifFrame> {
+    read_gif(&[
+        (0, 0, SIZE, 1, DisposalMethod::Keep),
+        (1, 1, 2, 2, dispose),
+        (0, 0, 1, 1, DisposalMethod::Keep),
+    ])
+}
+
+#[test]
+fn keep_leaves_frame_on_canvas() {
+    let frames = read_with_square_disposal(DisposalMethod::Keep);
+    assert_eq!(frames[1].image.get(1, 1), GREEN);
+    assert_eq!(frames[2].image.get(1, 1), GREEN);
+    assert_eq!(frames[2].image.get(3, 3), RED);
+}
+
+#[test]
+fn unspecified_disposal_acts_like_keep() {
+    let frames = read_with_square_disposal(DisposalMethod::Any);
+    assert_eq!(frames[2].image.get(2, 2), GREEN);
+    assert_eq!(frames[2].image.get(0, 3), RED);
+}
+
+#[test]
+fn background_only_clears_frame_rectangle() {
+    let frames = read_with_square_disposal(DisposalMethod::Background);
+    assert_eq!(frames[1].image.get(1, 1), GREEN);
+    for (i, j) in [(1, 1), (1, 2), (2, 1), (2, 2)] {
+        assert_eq!(frames[2].image.get(i, j), Rgba::TRANSPARENT);
+    }
+    for (i, j) in [(0, 3), (3, 0), (3, 3), (0, 1)] {
+        assert_eq!(frames[2].image.get(i, j), RED);
+    }
+}
+
+#[test]
+fn previous_restores_frame_rectangle() {
+    let frames = read_with_square_disposal(DisposalMethod::Previous);
+    assert_eq!(frames[1].image.get(2, 2), GREEN);
+    for i in 0..SIZE as usize {
+        for j in 0..SIZE as usize {
+            assert_eq!(frames[2].image.get(i, j), RED);
+        }
+    }
+}
+
+#[test]
+fn previous_on_first_frame_restores_transparent_canvas() {
+    let frames = read_gif(&[
+        (1, 1, 2, 2, DisposalMethod::Previous),
+        (0, 0, 1, 1, DisposalMethod::Keep),
+    ]);
+    assert_eq!(frames[0].image.get(1, 1), GREEN);
+    assert_eq!(frames[1].image.get(1, 1), Rgba::TRANSPARENT);
+    assert_eq!(frames[1].image.get(0, 0), RED);
+}
+
+#[test]
+fn transparent_index_shows_canvas_beneath() {
+    let frames = read_gif_with_transparency(
+        &[
+            (0, 0, 2, 3, DisposalMethod::Keep),
+            (0, 0, SIZE, 1, DisposalMethod::Keep),
+            (0, 0, 2, 3, DisposalMethod::Keep),
+        ],
+        Some(3),
+    );
+    assert!(frames[0].image.get(0, 0).is_transparent());
+    assert!(frames[0].image.get(3, 3).is_transparent());
+    assert_eq!(frames[2].image.get(0, 0), RED);
+}
+
+#[test]
+fn background_clears_to_transparent() {
+    let frames = read_gif(&[
+        (0, 0, SIZE, 2, DisposalMethod::Background),
+        (1, 1, 1, 1, DisposalMethod::Keep),
+    ]);
+    assert_eq!(frames[0].image.get(0, 0), GREEN);
+    assert!(frames[1].image.get(0, 0).is_transparent());
+    assert_eq!(frames[1].image.get(1, 1), RED);
+}
+
+#[test]
+fn oversized_frame_is_clipped() {
+    let frames = read_gif(&[
+        (0, 0, SIZE, 1, DisposalMethod::Keep),
+        (2, 2, SIZE, 2, DisposalMethod::Background),
+        (0, 0, 1, 1, DisposalMethod::Keep),
+    ]);
+    let image = &frames[1].image;
+    assert_eq!((image.height, image.width), (SIZE as usize, SIZE as usize));
+    assert_eq!(image.get(3, 3), GREEN);
+    assert_eq!(image.get(1, 1), RED);
+    assert!(frames[2].image.get(3, 3).is_transparent());
+}
+
+#[test]
+fn oversized_frame_expands_canvas() {
+    let frames = read_gif_with_options(
+        &[
+            (0, 0, SIZE, 1, DisposalMethod::Keep),
+            (3, 2, SIZE, 2, DisposalMethod::Keep),
+        ],
+        None,
+        CanvasFit::Expand,
+    );
+    for frame in &frames {
+        assert_eq!((frame.image.height, frame.image.width), (7, 6));
+    }
+    assert!(frames[0].image.get(6, 5).is_transparent());
+    assert_eq!(frames[0].image.get(3, 3), RED);
+    assert_eq!(frames[1].image.get(6, 5), GREEN);
+    assert_eq!(frames[1].image.get(0, 0), RED);
+}
+
+#[test]
+fn interlaced_frame_round_trips() {
+    // interlaced rows are stored in the order 0, 2, 1, 3 for a 4 row frame
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut bytes, SIZE, SIZE, &PALETTE).unwrap();
+        let buffer = [0, 2, 1, 3].map(|row| [row; SIZE as usize]).concat();
+        encoder
+            .write_frame(&Frame {
+                width: SIZE,
+                height: SIZE,
+                interlaced: true,
+                buffer: Cow::Owned(buffer),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    let read = |bytes: &[u8]| -> Vec<GifFrame> {
+        GifReader::new(bytes, DecodeMode::Strict, CanvasFit::Clip)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    };
+    let expected_rows: Vec<Rgba> = PALETTE
+        .chunks_exact(3)
+        .map(|c| Rgba::new(c[0], c[1], c[2], 255))
+        .collect();
+    let frames = read(&bytes);
+    assert!(frames[0].interlaced);
+    for (i, &expected) in expected_rows.iter().enumerate() {
+        assert_eq!(frames[0].image.get(i, 0), expected);
+    }
+
+    let palette = frames[0].palette.clone();
+    let pixels = SIZE as usize * SIZE as usize;
+    let output = frames
+        .into_iter()
+        .map(|frame| (frame, vec![false; pixels], DisposalMethod::Keep));
+    let mut written = Vec::new();
+    let mut writer = GifWriter::new(
+        output,
+        palette,
+        false,
+        SIZE as usize,
+        SIZE as usize,
+        &GifMetadata::default(),
+        &mut written,
+    )
+    .unwrap();
+    while writer.write_frame().unwrap() {}
+    writer.finish().unwrap();
+
+    let mut decoder = DecodeOptions::new().read_info(written.as_slice()).unwrap();
+    assert!(decoder.next_frame_info().unwrap().unwrap().interlaced);
+    let frames = read(&written);
+    assert!(frames[0].interlaced);
+    for (i, &expected) in expected_rows.iter().enumerate() {
+        assert_eq!(frames[0].image.get(i, 0), expected);
+    }
+}
+
+#[test]
+fn local_palettes_round_trip() {
+    let size = SIZE as usize;
+    let blue = Rgba::new(0, 0, 255, 255);
+    let red_frame = GifFrame::new(
+        Image {
+            buffer: vec![RED; size * size],
+            height: size,
+            width: size,
+        },
+        vec![RED.rgb()],
+        10,
+    );
+    // the top rows turn green and the bottom rows blue, except for the corner, which stays red
+    let mut pixels = vec![GREEN; size * size / 2];
+    pixels.extend(vec![blue; size * size / 2]);
+    pixels[0] = RED;
+    let mut transparent = vec![false; size * size];
+    transparent[0] = true;
+    let palette = vec![GREEN.rgb(), blue.rgb()];
+    let green_frame = GifFrame::new(
+        Image {
+            buffer: pixels,
+            height: size,
+            width: size,
+        },
+        palette.clone(),
+        10,
+    );
+    let output = [
+        (red_frame, vec![false; size * size], DisposalMethod::Keep),
+        (green_frame, transparent, DisposalMethod::Keep),
+    ];
+    let mut written = Vec::new();
+    let mut writer = GifWriter::new(
+        output.into_iter(),
+        vec![RED.rgb()],
+        true,
+        size,
+        size,
+        &GifMetadata::default(),
+        &mut written,
+    )
+    .unwrap();
+    while writer.write_frame().unwrap() {}
+    writer.finish().unwrap();
+
+    let mut decoder = DecodeOptions::new().read_info(written.as_slice()).unwrap();
+    let first = decoder.read_next_frame().unwrap().unwrap();
+    // the first frame's palette is the global one, so it doesn't get its own
+    assert!(first.palette.is_none());
+    let second = decoder.read_next_frame().unwrap().unwrap();
+    let colour_table = second.palette.as_ref().unwrap();
+    assert_eq!(colour_table[..6], [0, 255, 0, 0, 0, 255]);
+    assert_eq!(second.transparent, Some(palette.len() as u8));
+    assert_eq!(second.buffer[0], palette.len() as u8);
+
+    let frames: Vec<GifFrame> =
+        GifReader::new(written.as_slice(), DecodeMode::Strict, CanvasFit::Clip)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+    assert_eq!(frames[0].image.get(0, 0), RED);
+    assert_eq!(frames[1].image.get(0, 0), RED);
+    assert_eq!(frames[1].image.get(0, 1), GREEN);
+    assert_eq!(frames[1].image.get(3, 3), blue);
+}
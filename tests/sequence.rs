@@ -0,0 +1,107 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use gif_compressor::{
+    frame_rate::FrameRate,
+    reader::DecodeMode,
+    sequence::{self, PngSequence},
+};
+
+/// an empty directory for the test to write frames into
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sequence-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// writes a 1x1 png whose red channel tells frames apart
+fn write_png(dir: &Path, name: &str, tag: u8) {
+    let mut encoder = png::Encoder::new(File::create(dir.join(name)).unwrap(), 1, 1);
+    encoder.set_color(png::ColorType::Rgb);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&[tag, 0, 0]).unwrap();
+}
+
+/// the tag of each frame, in the order they're read
+fn read_tags(input: &str) -> Vec<u8> {
+    PngSequence::new(input, FrameRate::default(), DecodeMode::Strict)
+        .unwrap()
+        .map(|frame| frame.unwrap().image.get(0, 0).r)
+        .collect()
+}
+
+#[test]
+fn directory_is_read_in_natural_order() {
+    let dir = temp_dir("natural");
+    for (tag, name) in [
+        "frame1", "frame2", "frame9", "frame010", "frame10a", "frame100",
+    ]
+    .iter()
+    .enumerate()
+    {
+        write_png(&dir, &format!("{name}.png"), tag as u8);
+    }
+    fs::write(dir.join("notes.txt"), "not a frame").unwrap();
+    assert_eq!(read_tags(dir.to_str().unwrap()), [0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn pattern_starting_at_0() {
+    let dir = temp_dir("from-0");
+    for index in 0..3 {
+        write_png(&dir, &format!("f_{index:03}.png"), index);
+    }
+    let pattern = dir.join("f_%03d.png");
+    assert!(sequence::is_pattern(pattern.to_str().unwrap()));
+    assert_eq!(read_tags(pattern.to_str().unwrap()), [0, 1, 2]);
+}
+
+#[test]
+fn pattern_starting_at_1_stops_at_a_gap() {
+    let dir = temp_dir("from-1");
+    for index in [1, 2, 3, 5] {
+        write_png(&dir, &format!("f_{index}.png"), index);
+    }
+    let pattern = dir.join("f_%d.png");
+    assert_eq!(read_tags(pattern.to_str().unwrap()), [1, 2, 3]);
+}
+
+#[test]
+fn pattern_with_missing_first_index() {
+    let dir = temp_dir("missing");
+    for index in [4, 5] {
+        write_png(&dir, &format!("f_{index:02}.png"), index);
+    }
+    let pattern = dir.join("f_%02d.png");
+    assert_eq!(read_tags(pattern.to_str().unwrap()), [4, 5]);
+
+    // like ffmpeg, only the first five indices are tried for the start
+    let dir = temp_dir("too-late");
+    write_png(&dir, "f_05.png", 5);
+    let pattern = dir.join("f_%02d.png");
+    assert!(
+        PngSequence::new(
+            pattern.to_str().unwrap(),
+            FrameRate::default(),
+            DecodeMode::Strict
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn percent_without_conversion_is_not_a_pattern() {
+    assert!(!sequence::is_pattern("50%off.gif"));
+    assert!(!sequence::is_pattern("f_%xd.png"));
+    assert!(sequence::is_pattern("f_%d.png"));
+
+    // an existing file is read as itself, even if its name looks like a pattern
+    let dir = temp_dir("literal");
+    write_png(&dir, "x%dy.png", 0);
+    assert!(!sequence::is_pattern(
+        dir.join("x%dy.png").to_str().unwrap()
+    ));
+}
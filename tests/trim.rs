@@ -0,0 +1,97 @@
+use std::cell::Cell;
+
+use gif_compressor::{
+    error::{Error, Result},
+    image::{GifFrame, Image, Rgba},
+    trim::{FrameRange, Trim},
+};
+
+/// a 1x1 frame whose red channel tells frames apart
+fn frame(tag: u8, delay: u16) -> GifFrame {
+    let mut image = Image::blank(1, 1);
+    *image.get_mut(0, 0) = Rgba::new(tag, 0, 0, 255);
+    GifFrame::new(image, Vec::new(), delay)
+}
+
+/// tags each frame with its index
+fn frames(delays: &[u16]) -> Vec<Result<GifFrame>> {
+    delays
+        .iter()
+        .enumerate()
+        .map(|(i, &delay)| Ok(frame(i as u8, delay)))
+        .collect()
+}
+
+/// (tag, delay) of each kept frame
+fn trim(delays: &[u16], range: FrameRange) -> Vec<(u8, u16)> {
+    Trim::new(frames(delays).into_iter(), range)
+        .map(|frame| {
+            let frame = frame.unwrap();
+            (frame.image.get(0, 0).r, frame.delay)
+        })
+        .collect()
+}
+
+#[test]
+fn index_range() {
+    assert_eq!(
+        trim(&[10, 20, 30, 40], FrameRange::Index(1..3)),
+        [(1, 20), (2, 30)]
+    );
+}
+
+#[test]
+fn stops_pulling_past_the_range() {
+    let pulled = Cell::new(0);
+    let source = frames(&[10; 6])
+        .into_iter()
+        .inspect(|_| pulled.set(pulled.get() + 1));
+    assert_eq!(Trim::new(source, FrameRange::Index(0..2)).count(), 2);
+    assert_eq!(pulled.get(), 2);
+
+    pulled.set(0);
+    let source = frames(&[10; 6])
+        .into_iter()
+        .inspect(|_| pulled.set(pulled.get() + 1));
+    assert_eq!(Trim::new(source, FrameRange::Time(0..25)).count(), 3);
+    assert_eq!(pulled.get(), 3);
+}
+
+#[test]
+fn partial_first_and_last_frames_are_shortened() {
+    // shown over 0-10, 10-20, 20-30 and 30-40
+    assert_eq!(
+        trim(&[10, 10, 10, 10], FrameRange::Time(5..25)),
+        [(0, 5), (1, 10), (2, 5)]
+    );
+}
+
+#[test]
+fn frames_touching_the_range_are_dropped() {
+    assert_eq!(
+        trim(&[10, 10, 10, 10], FrameRange::Time(10..30)),
+        [(1, 10), (2, 10)]
+    );
+}
+
+#[test]
+fn zero_delay_frames_are_kept_if_shown_in_range() {
+    // shown at 0, over 0-10, at 10, over 10-20 and at 20
+    assert_eq!(
+        trim(&[0, 10, 0, 10, 0], FrameRange::Time(5..20)),
+        [(1, 5), (2, 0), (3, 10)]
+    );
+    assert_eq!(
+        trim(&[0, 10, 0, 10, 0], FrameRange::Time(0..10)),
+        [(0, 0), (1, 10)]
+    );
+}
+
+#[test]
+fn errors_pass_through() {
+    let mut source = frames(&[10, 10, 10]);
+    source[1] = Err(Error::NoFrames);
+    let trimmed: Vec<_> = Trim::new(source.into_iter(), FrameRange::default()).collect();
+    assert_eq!(trimmed.len(), 3);
+    assert!(trimmed[1].is_err());
+}
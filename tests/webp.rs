@@ -0,0 +1,191 @@
+use std::{io::Cursor, num::NonZeroU16};
+
+use gif::{DisposalMethod, Repeat};
+use gif_compressor::{
+    image::{GifFrame, Image, Rgba},
+    metadata::GifMetadata,
+    webp::WebpWriter,
+};
+use image_webp::{LoopCount, WebPDecoder};
+
+const SIZE: usize = 4;
+const RED: Rgba = Rgba {
+    r: 255,
+    g: 0,
+    b: 0,
+    a: 255,
+};
+const GREEN: Rgba = Rgba {
+    r: 0,
+    g: 255,
+    b: 0,
+    a: 255,
+};
+
+/// (top, left, size, colour, delay, disposal), drawn over a copy of the previous frame's canvas
+type SolidFrame = (usize, usize, usize, Rgba, u16, DisposalMethod);
+
+fn write_webp(frames: &[SolidFrame], repeat: Repeat) -> Vec<u8> {
+    let mut canvas = Image::blank(SIZE, SIZE);
+    let output: Vec<_> = frames
+        .iter()
+        .map(|&(top, left, size, colour, delay, dispose)| {
+            for i in top..top + size {
+                for j in left..left + size {
+                    *canvas.get_mut(i, j) = colour;
+                }
+            }
+            let frame = GifFrame {
+                top,
+                left,
+                local_height: size,
+                local_width: size,
+                ..GifFrame::new(canvas.clone(), Vec::new(), delay)
+            };
+            (frame, vec![false; SIZE * SIZE], dispose)
+        })
+        .collect();
+    let metadata = GifMetadata {
+        repeat,
+        ..GifMetadata::default()
+    };
+    let mut bytes = Vec::new();
+    let mut writer =
+        WebpWriter::new(output.into_iter(), SIZE, SIZE, &metadata, &mut bytes).unwrap();
+    while writer.write_frame().unwrap() {}
+    writer.finish().unwrap();
+    bytes
+}
+
+/// (fourcc, data) of each top-level chunk after the RIFF header
+fn chunks(bytes: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    assert_eq!(&bytes[..4], b"RIFF");
+    assert_eq!(&bytes[8..12], b"WEBP");
+    let riff_len = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+    assert_eq!(riff_len + 8, bytes.len());
+    let mut ans = Vec::new();
+    let mut rest = &bytes[12..];
+    while !rest.is_empty() {
+        let fourcc = rest[..4].try_into().unwrap();
+        let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        ans.push((fourcc, &rest[8..8 + len]));
+        rest = &rest[(8 + len + len % 2).min(rest.len())..];
+    }
+    ans
+}
+
+fn u24(bytes: &[u8]) -> usize {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) as usize
+}
+
+/// the decoder blends every frame over the background, which can be off by one
+fn assert_close(actual: [u8; 4], expected: [u8; 4]) {
+    assert!(
+        actual
+            .iter()
+            .zip(expected)
+            .all(|(&a, e)| a.abs_diff(e) <= 1),
+        "{actual:?} isn't close to {expected:?}"
+    );
+}
+
+fn three_frames() -> Vec<u8> {
+    write_webp(
+        &[
+            (0, 0, SIZE, RED, 10, DisposalMethod::Keep),
+            // odd offsets, so the frame grows up and left to (0, 2)
+            (1, 3, 1, GREEN, 25, DisposalMethod::Background),
+            (2, 2, 2, GREEN, 5, DisposalMethod::Keep),
+        ],
+        Repeat::Finite(2),
+    )
+}
+
+#[test]
+fn chunks_have_expected_flags_and_offsets() {
+    let bytes = three_frames();
+    let chunks = chunks(&bytes);
+    let fourccs: Vec<_> = chunks.iter().map(|(fourcc, _)| fourcc).collect();
+    assert_eq!(fourccs, [b"VP8X", b"ANIM", b"ANMF", b"ANMF", b"ANMF"]);
+
+    let vp8x = chunks[0].1;
+    // animation and alpha
+    assert_eq!(vp8x[0], 0b0001_0010);
+    assert_eq!((u24(&vp8x[4..]), u24(&vp8x[7..])), (SIZE - 1, SIZE - 1));
+    let anim = chunks[1].1;
+    assert_eq!(u16::from_le_bytes([anim[4], anim[5]]), 3);
+
+    // (x / 2, y / 2, width - 1, height - 1, duration, flags)
+    let frames: Vec<_> = chunks[2..]
+        .iter()
+        .map(|(_, anmf)| {
+            // the frame holds a bare VP8L chunk, without the RIFF header of a still webp
+            assert_eq!(&anmf[16..20], b"VP8L");
+            (
+                u24(&anmf[0..]),
+                u24(&anmf[3..]),
+                u24(&anmf[6..]),
+                u24(&anmf[9..]),
+                u24(&anmf[12..]),
+                anmf[15],
+            )
+        })
+        .collect();
+    assert_eq!(
+        frames,
+        [
+            (0, 0, 3, 3, 100, 0),
+            (1, 0, 1, 1, 250, 1),
+            (1, 1, 1, 1, 50, 0),
+        ]
+    );
+}
+
+#[test]
+fn decodes_back() {
+    let bytes = three_frames();
+    let mut decoder = WebPDecoder::new(Cursor::new(bytes)).unwrap();
+    assert!(decoder.is_animated());
+    assert_eq!(decoder.dimensions(), (SIZE as u32, SIZE as u32));
+    assert_eq!(decoder.num_frames(), 3);
+    assert_eq!(
+        decoder.loop_count(),
+        LoopCount::Times(NonZeroU16::new(3).unwrap())
+    );
+    // the background is only a hint, which the decoder doesn't dispose to unless told to
+    let background = decoder.background_color_hint().unwrap();
+    assert_eq!(background, [0; 4]);
+    decoder.set_background_color(background).unwrap();
+
+    let mut buffer = vec![0; decoder.output_buffer_size().unwrap()];
+    let pixel = |buffer: &[u8], i: usize, j: usize| -> [u8; 4] {
+        buffer[4 * (i * SIZE + j)..][..4].try_into().unwrap()
+    };
+    let mut durations = Vec::new();
+    durations.push(decoder.read_frame(&mut buffer).unwrap());
+    assert_close(pixel(&buffer, 3, 3), [255, 0, 0, 255]);
+
+    durations.push(decoder.read_frame(&mut buffer).unwrap());
+    assert_close(pixel(&buffer, 1, 3), [0, 255, 0, 255]);
+    // the padding added by moving the frame to an even offset is transparent, showing the canvas
+    assert_close(pixel(&buffer, 0, 2), [255, 0, 0, 255]);
+    assert_close(pixel(&buffer, 1, 2), [255, 0, 0, 255]);
+
+    durations.push(decoder.read_frame(&mut buffer).unwrap());
+    // the previous frame's rectangle, including its padding, was cleared
+    assert_eq!(pixel(&buffer, 0, 2)[3], 0);
+    assert_eq!(pixel(&buffer, 1, 3)[3], 0);
+    assert_close(pixel(&buffer, 3, 3), [0, 255, 0, 255]);
+    assert_close(pixel(&buffer, 0, 0), [255, 0, 0, 255]);
+    assert_eq!(durations, [100, 250, 50]);
+}
+
+#[test]
+fn infinite_loop_count() {
+    let bytes = write_webp(
+        &[(0, 0, SIZE, RED, 10, DisposalMethod::Keep)],
+        Repeat::Infinite,
+    );
+    let decoder = WebPDecoder::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(decoder.loop_count(), LoopCount::Forever);
+}
@@ -0,0 +1,167 @@
+use gif_compressor::{
+    frame_rate::FrameRate,
+    image::{GifFrame, Rgba},
+    reader::DecodeMode,
+    y4m::{Y4mReader, YuvMatrix},
+};
+
+/// red, green, blue, white and black
+const PRIMARIES: [[u8; 3]; 5] = [
+    [255, 0, 0],
+    [0, 255, 0],
+    [0, 0, 255],
+    [255, 255, 255],
+    [0, 0, 0],
+];
+/// 8 bit yuv is only accurate to within a few rgb steps
+const TOLERANCE: i32 = 3;
+
+fn read_y4m(header: &str, planes: &[u8], matrix: YuvMatrix) -> Vec<GifFrame> {
+    let mut bytes = format!("YUV4MPEG2 {header}\nFRAME\n").into_bytes();
+    bytes.extend_from_slice(planes);
+    Y4mReader::new(
+        bytes.as_slice(),
+        FrameRate::default(),
+        Some(matrix),
+        DecodeMode::Strict,
+    )
+    .unwrap()
+    .collect::<Result<_, _>>()
+    .unwrap()
+}
+
+fn assert_close(actual: Rgba, expected: [u8; 3]) {
+    let actual = [actual.r, actual.g, actual.b];
+    assert!(
+        actual
+            .iter()
+            .zip(expected)
+            .all(|(&a, e)| (a as i32 - e as i32).abs() <= TOLERANCE),
+        "{actual:?} isn't close to {expected:?}"
+    );
+}
+
+/// decodes one 444 row of the given (y, u, v) samples and checks it against the primaries
+fn check_primaries(yuv: [[u8; 3]; 5], matrix: YuvMatrix, full_range: bool) {
+    let range = if full_range { " XCOLORRANGE=FULL" } else { "" };
+    let planes: Vec<u8> = (0..3)
+        .flat_map(|channel| yuv.map(|sample| sample[channel]))
+        .collect();
+    let frames = read_y4m(&format!("W5 H1 F25:1 C444{range}"), &planes, matrix);
+    assert_eq!(frames.len(), 1);
+    for (j, expected) in PRIMARIES.into_iter().enumerate() {
+        assert_close(frames[0].image.get(0, j), expected);
+    }
+}
+
+#[test]
+fn bt601_limited_range() {
+    check_primaries(
+        [
+            [81, 90, 240],
+            [145, 54, 34],
+            [41, 240, 110],
+            [235, 128, 128],
+            [16, 128, 128],
+        ],
+        YuvMatrix::Bt601,
+        false,
+    );
+}
+
+#[test]
+fn bt601_full_range() {
+    check_primaries(
+        [
+            [76, 85, 255],
+            [150, 44, 21],
+            [29, 255, 107],
+            [255, 128, 128],
+            [0, 128, 128],
+        ],
+        YuvMatrix::Bt601,
+        true,
+    );
+}
+
+#[test]
+fn bt709_limited_range() {
+    check_primaries(
+        [
+            [63, 102, 240],
+            [173, 42, 26],
+            [32, 240, 118],
+            [235, 128, 128],
+            [16, 128, 128],
+        ],
+        YuvMatrix::Bt709,
+        false,
+    );
+}
+
+#[test]
+fn bt709_full_range() {
+    check_primaries(
+        [
+            [54, 99, 255],
+            [182, 30, 12],
+            [18, 255, 116],
+            [255, 128, 128],
+            [0, 128, 128],
+        ],
+        YuvMatrix::Bt709,
+        true,
+    );
+}
+
+#[test]
+fn limited_range_clamps_out_of_range_luma() {
+    let frames = read_y4m(
+        "W2 H1 C444",
+        &[0, 255, 128, 128, 128, 128],
+        YuvMatrix::Bt601,
+    );
+    assert_eq!(frames[0].image.get(0, 0), Rgba::new(0, 0, 0, 255));
+    assert_eq!(frames[0].image.get(0, 1), Rgba::new(255, 255, 255, 255));
+}
+
+#[test]
+fn odd_width_420_rounds_chroma_up() {
+    // 3x3 luma with 2x2 chroma, so the last column and row have chroma samples to themselves
+    let [red, green, blue, white] = [
+        [76, 85, 255],
+        [150, 44, 21],
+        [29, 255, 107],
+        [255, 128, 128],
+    ];
+    let blocks = [[white, red], [blue, green]];
+    let expected = [[PRIMARIES[3], PRIMARIES[0]], [PRIMARIES[2], PRIMARIES[1]]];
+    let mut planes = Vec::new();
+    for i in 0..3 {
+        for j in 0..3 {
+            planes.push(blocks[i / 2][j / 2][0]);
+        }
+    }
+    for channel in 1..3 {
+        for row in &blocks {
+            planes.extend(row.map(|sample| sample[channel]));
+        }
+    }
+    // a second frame, which is only read correctly if the first one's size was right
+    let mut two_frames = planes.clone();
+    two_frames.extend_from_slice(b"FRAME\n");
+    two_frames.extend_from_slice(&planes);
+    let frames = read_y4m(
+        "W3 H3 C420jpeg XCOLORRANGE=FULL",
+        &two_frames,
+        YuvMatrix::Bt601,
+    );
+    assert_eq!(frames.len(), 2);
+    for frame in &frames {
+        for i in 0..3 {
+            for j in 0..3 {
+                assert_close(frame.image.get(i, j), expected[i / 2][j / 2]);
+            }
+        }
+    }
+}